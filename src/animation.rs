@@ -0,0 +1,723 @@
+use std::error;
+use std::fmt;
+use std::ops::Index;
+
+use {FrameBlend, Vec3};
+use token::{Token, Tokenizer};
+
+/// The player animations, numbered as in the game's `animNumber_t`.
+///
+/// The first 31 are read from `animation.cfg`; the rest are derived from
+/// them or fixed by the game.
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Hash)]
+pub enum AnimationId {
+    BothDeath1,
+    BothDead1,
+    BothDeath2,
+    BothDead2,
+    BothDeath3,
+    BothDead3,
+    TorsoGesture,
+    TorsoAttack,
+    TorsoAttack2,
+    TorsoDrop,
+    TorsoRaise,
+    TorsoStand,
+    TorsoStand2,
+    LegsWalkCr,
+    LegsWalk,
+    LegsRun,
+    LegsBack,
+    LegsSwim,
+    LegsJump,
+    LegsLand,
+    LegsJumpB,
+    LegsLandB,
+    LegsIdle,
+    LegsIdleCr,
+    LegsTurn,
+    TorsoGetFlag,
+    TorsoGuardBase,
+    TorsoPatrol,
+    TorsoFollowMe,
+    TorsoAffirmative,
+    TorsoNegative,
+    // The game reserves 31 for `MAX_ANIMATIONS`.
+    LegsBackCr = 32,
+    LegsBackWalk,
+    FlagRun,
+    FlagStand,
+    FlagStand2Run,
+}
+
+/// Number of animations listed in `animation.cfg`.
+pub const MAX_ANIMATIONS: usize = 31;
+
+/// Size of the game's animation table, including the derived animations.
+pub const MAX_TOTALANIMATIONS: usize = 37;
+
+impl AnimationId {
+    pub const ALL: [AnimationId; MAX_TOTALANIMATIONS - 1] =
+        [AnimationId::BothDeath1,
+         AnimationId::BothDead1,
+         AnimationId::BothDeath2,
+         AnimationId::BothDead2,
+         AnimationId::BothDeath3,
+         AnimationId::BothDead3,
+         AnimationId::TorsoGesture,
+         AnimationId::TorsoAttack,
+         AnimationId::TorsoAttack2,
+         AnimationId::TorsoDrop,
+         AnimationId::TorsoRaise,
+         AnimationId::TorsoStand,
+         AnimationId::TorsoStand2,
+         AnimationId::LegsWalkCr,
+         AnimationId::LegsWalk,
+         AnimationId::LegsRun,
+         AnimationId::LegsBack,
+         AnimationId::LegsSwim,
+         AnimationId::LegsJump,
+         AnimationId::LegsLand,
+         AnimationId::LegsJumpB,
+         AnimationId::LegsLandB,
+         AnimationId::LegsIdle,
+         AnimationId::LegsIdleCr,
+         AnimationId::LegsTurn,
+         AnimationId::TorsoGetFlag,
+         AnimationId::TorsoGuardBase,
+         AnimationId::TorsoPatrol,
+         AnimationId::TorsoFollowMe,
+         AnimationId::TorsoAffirmative,
+         AnimationId::TorsoNegative,
+         AnimationId::LegsBackCr,
+         AnimationId::LegsBackWalk,
+         AnimationId::FlagRun,
+         AnimationId::FlagStand,
+         AnimationId::FlagStand2Run];
+
+    /// The game's `animNumber_t` value.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_index(index: usize) -> Option<AnimationId> {
+        AnimationId::ALL.iter().cloned().find(|id| id.index() == index)
+    }
+}
+
+/// One animation range, as the game's `animation_t`.
+#[derive(Debug,Copy,Clone,PartialEq,Default)]
+pub struct Animation {
+    pub first_frame: usize,
+    pub num_frames: usize,
+    /// Frames from the end that repeat; 0 plays the animation once.
+    pub loop_frames: usize,
+    /// Milliseconds between frames.
+    pub frame_lerp: i32,
+    /// Milliseconds to blend into the first frame.
+    pub initial_lerp: i32,
+    /// Play from the last frame to the first.
+    pub reversed: bool,
+    /// Play forward, then backward.
+    pub flipflop: bool,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Footsteps {
+    Normal,
+    Boot,
+    Flesh,
+    Mech,
+    Energy,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Sex {
+    Male,
+    Female,
+    Neuter,
+}
+
+/// A parsed `animation.cfg`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct AnimationConfig {
+    pub footsteps: Footsteps,
+    pub head_offset: Vec3,
+    pub sex: Sex,
+    pub fixed_legs: bool,
+    pub fixed_torso: bool,
+    /// Indexed by `AnimationId::index`; entry 31 is unused.
+    pub animations: [Animation; MAX_TOTALANIMATIONS],
+}
+
+impl Index<AnimationId> for AnimationConfig {
+    type Output = Animation;
+
+    fn index(&self, id: AnimationId) -> &Animation {
+        &self.animations[id.index()]
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum AnimationErrorKind {
+    /// A word before the animations that is not a known keyword.
+    UnknownKeyword(String),
+    BadFootsteps(String),
+    ExpectedInteger(String),
+    ExpectedNumber(String),
+    /// A legs animation starts before the first legs-only frame.
+    NegativeFrame(i32),
+    /// A loop frame count below zero.
+    NegativeLoopFrames(i32),
+    /// The file ends inside the named field.
+    UnexpectedEnd { expected: &'static str },
+}
+
+/// A malformed `animation.cfg`. Lines and columns count from 1.
+#[derive(Debug,Clone,PartialEq)]
+pub struct AnimationError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AnimationErrorKind,
+}
+
+impl fmt::Display for AnimationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AnimationErrorKind::UnknownKeyword(ref token) => {
+                write!(f, "unknown keyword '{}'", token)
+            }
+            AnimationErrorKind::BadFootsteps(ref token) => {
+                write!(f, "unknown footsteps '{}'", token)
+            }
+            AnimationErrorKind::ExpectedInteger(ref token) => {
+                write!(f, "expected an integer, found '{}'", token)
+            }
+            AnimationErrorKind::ExpectedNumber(ref token) => {
+                write!(f, "expected a number, found '{}'", token)
+            }
+            AnimationErrorKind::NegativeFrame(frame) => {
+                write!(f, "legs animation starts at frame {}", frame)
+            }
+            AnimationErrorKind::NegativeLoopFrames(count) => {
+                write!(f, "loop frame count {} is negative", count)
+            }
+            AnimationErrorKind::UnexpectedEnd { expected } => {
+                write!(f, "file ends where {} was expected", expected)
+            }
+        }
+    }
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.kind, self.line, self.column)
+    }
+}
+
+impl error::Error for AnimationError {}
+
+fn expect<'a>(tokens: &mut Tokenizer<'a>,
+             expected: &'static str)
+             -> Result<Token<'a>, AnimationError> {
+    match tokens.next() {
+        Some(token) => Ok(token),
+        None => Err(error_here(tokens, AnimationErrorKind::UnexpectedEnd { expected })),
+    }
+}
+
+fn error_here(tokens: &Tokenizer, kind: AnimationErrorKind) -> AnimationError {
+    let (line, column) = tokens.location();
+    AnimationError { line, column, kind }
+}
+
+fn error_at(token: &Token, kind: AnimationErrorKind) -> AnimationError {
+    AnimationError {
+        line: token.line,
+        column: token.column,
+        kind,
+    }
+}
+
+fn integer(token: &Token) -> Result<i32, AnimationError> {
+    token.text
+        .parse()
+        .map_err(|_| error_at(token, AnimationErrorKind::ExpectedInteger(token.text.to_string())))
+}
+
+fn number(token: &Token) -> Result<f32, AnimationError> {
+    token.text
+        .parse()
+        .map_err(|_| error_at(token, AnimationErrorKind::ExpectedNumber(token.text.to_string())))
+}
+
+impl AnimationConfig {
+    /// Parses an `animation.cfg` and derives the remaining animations, as
+    /// `CG_ParseAnimationFile` does.
+    ///
+    /// Legs animations are renumbered so that they do not count the frames
+    /// that only the torso model has. Files that stop after `LEGS_TURN`
+    /// get the team arena torso animations copied from `TORSO_GESTURE`.
+    pub fn parse(text: &str) -> Result<AnimationConfig, AnimationError> {
+        let mut tokens = Tokenizer::new(text);
+        let mut config = AnimationConfig {
+            footsteps: Footsteps::Normal,
+            head_offset: Vec3::ZERO,
+            sex: Sex::Male,
+            fixed_legs: false,
+            fixed_torso: false,
+            animations: [Animation::default(); MAX_TOTALANIMATIONS],
+        };
+
+        // Keywords come first; the animations start at the first number.
+        while let Some(token) = tokens.peek() {
+            if token.text.as_bytes().first().is_some_and(u8::is_ascii_digit) {
+                break;
+            }
+            tokens.next();
+            let keyword = token.text.to_ascii_lowercase();
+            match &keyword[..] {
+                "footsteps" => {
+                    let value = expect(&mut tokens, "a footsteps type")?;
+                    config.footsteps = match &value.text.to_ascii_lowercase()[..] {
+                        "default" | "normal" => Footsteps::Normal,
+                        "boot" => Footsteps::Boot,
+                        "flesh" => Footsteps::Flesh,
+                        "mech" => Footsteps::Mech,
+                        "energy" => Footsteps::Energy,
+                        _ => {
+                            return Err(error_at(&value,
+                                                AnimationErrorKind::BadFootsteps(value.text
+                                                    .to_string())))
+                        }
+                    };
+                }
+                "headoffset" => {
+                    for i in 0..3 {
+                        config.head_offset[i] = number(&expect(&mut tokens, "a head offset")?)?;
+                    }
+                }
+                "sex" => {
+                    let value = expect(&mut tokens, "a sex")?;
+                    config.sex = match value.text.as_bytes().first() {
+                        Some(b'f') | Some(b'F') => Sex::Female,
+                        Some(b'n') | Some(b'N') => Sex::Neuter,
+                        _ => Sex::Male,
+                    };
+                }
+                "fixedlegs" => config.fixed_legs = true,
+                "fixedtorso" => config.fixed_torso = true,
+                _ => {
+                    return Err(error_at(&token,
+                                        AnimationErrorKind::UnknownKeyword(token.text
+                                            .to_string())))
+                }
+            }
+        }
+
+        let gesture = AnimationId::TorsoGesture.index();
+        let walk_cr = AnimationId::LegsWalkCr.index();
+        let get_flag = AnimationId::TorsoGetFlag.index();
+        let mut skip = 0;
+        for i in 0..MAX_ANIMATIONS {
+            let token = match tokens.next() {
+                Some(token) => token,
+                None if i >= get_flag => {
+                    config.animations[i] = Animation {
+                        reversed: false,
+                        flipflop: false,
+                        ..config.animations[gesture]
+                    };
+                    continue;
+                }
+                None => {
+                    return Err(error_here(&tokens, AnimationErrorKind::UnexpectedEnd {
+                        expected: "a first frame",
+                    }))
+                }
+            };
+            let mut first_frame = integer(&token)?;
+            // Legs frames follow the torso-only frames in the cfg but not in
+            // the legs model.
+            if i == walk_cr {
+                skip = first_frame - config.animations[gesture].first_frame as i32;
+            }
+            if i >= walk_cr && i < get_flag {
+                first_frame -= skip;
+            }
+            if first_frame < 0 {
+                return Err(error_at(&token, AnimationErrorKind::NegativeFrame(first_frame)));
+            }
+
+            let token = expect(&mut tokens, "a frame count")?;
+            let num_frames = integer(&token)?;
+            let token = expect(&mut tokens, "a loop frame count")?;
+            let loop_frames = integer(&token)?;
+            if loop_frames < 0 {
+                return Err(error_at(&token, AnimationErrorKind::NegativeLoopFrames(loop_frames)));
+            }
+            let mut fps = number(&expect(&mut tokens, "a frame rate")?)?;
+            if fps == 0.0 {
+                fps = 1.0;
+            }
+            let frame_lerp = (1000.0 / fps) as i32;
+
+            config.animations[i] = Animation {
+                first_frame: first_frame as usize,
+                num_frames: num_frames.unsigned_abs() as usize,
+                loop_frames: loop_frames as usize,
+                frame_lerp,
+                initial_lerp: frame_lerp,
+                // A negative frame count plays the animation backwards.
+                reversed: num_frames < 0,
+                flipflop: false,
+            };
+        }
+
+        let back_cr = Animation {
+            reversed: true,
+            ..config[AnimationId::LegsWalkCr]
+        };
+        let back_walk = Animation {
+            reversed: true,
+            ..config[AnimationId::LegsWalk]
+        };
+        // The flag model's animations are fixed, as `CG_ParseAnimationFile`
+        // sets them.
+        let flag = |first_frame, num_frames, loop_frames, fps, reversed| {
+            Animation {
+                first_frame,
+                num_frames,
+                loop_frames,
+                frame_lerp: 1000 / fps,
+                initial_lerp: 1000 / fps,
+                reversed,
+                flipflop: false,
+            }
+        };
+        config.animations[AnimationId::LegsBackCr.index()] = back_cr;
+        config.animations[AnimationId::LegsBackWalk.index()] = back_walk;
+        config.animations[AnimationId::FlagRun.index()] = flag(0, 16, 16, 15, false);
+        config.animations[AnimationId::FlagStand.index()] = flag(16, 5, 0, 20, false);
+        config.animations[AnimationId::FlagStand2Run.index()] = flag(16, 5, 1, 15, true);
+        Ok(config)
+    }
+}
+
+/// Advances an animation over time, as the game's `lerpFrame_t` and
+/// `CG_RunLerpFrame` do.
+///
+/// Times are in milliseconds, like `cg.time`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct AnimationPlayer {
+    animation: Animation,
+    old_frame: i32,
+    frame: i32,
+    old_frame_time: i32,
+    frame_time: i32,
+    animation_time: i32,
+    backlerp: f32,
+}
+
+impl AnimationPlayer {
+    /// Starts at the first frame of `animation`, as `CG_ClearLerpFrame`.
+    pub fn new(animation: Animation, time: i32) -> AnimationPlayer {
+        let mut player = AnimationPlayer {
+            animation,
+            old_frame: 0,
+            frame: 0,
+            old_frame_time: time,
+            frame_time: time,
+            animation_time: 0,
+            backlerp: 0.0,
+        };
+        player.set_animation(animation);
+        player.old_frame = animation.first_frame as i32;
+        player.frame = animation.first_frame as i32;
+        player
+    }
+
+    /// Switches to `animation`, blending into it over its `initial_lerp`.
+    ///
+    /// Call this whenever the game would change `animationNumber`; calling
+    /// it with the current animation restarts it.
+    pub fn set_animation(&mut self, animation: Animation) {
+        self.animation = animation;
+        self.animation_time = self.frame_time + animation.initial_lerp;
+    }
+
+    pub fn animation(&self) -> &Animation {
+        &self.animation
+    }
+
+    /// The frames to show, as of the last `run`.
+    pub fn blend(&self) -> FrameBlend {
+        FrameBlend {
+            old_frame: self.old_frame.max(0) as usize,
+            frame: self.frame.max(0) as usize,
+            backlerp: self.backlerp,
+        }
+    }
+
+    /// Advances to `time`, playing `speed_scale` times faster than normal.
+    pub fn run(&mut self, time: i32, speed_scale: f32) -> FrameBlend {
+        let anim = self.animation;
+
+        // Once the current frame is reached it becomes the old frame.
+        if time >= self.frame_time {
+            self.old_frame = self.frame;
+            self.old_frame_time = self.frame_time;
+            if anim.frame_lerp == 0 {
+                return self.blend();
+            }
+
+            if time < self.animation_time {
+                self.frame_time = self.animation_time;
+            } else {
+                self.frame_time = self.old_frame_time + anim.frame_lerp;
+            }
+            let mut f = (self.frame_time - self.animation_time) / anim.frame_lerp;
+            f = (f as f32 * speed_scale) as i32;
+
+            let num_frames = anim.num_frames as i32;
+            let loop_frames = anim.loop_frames as i32;
+            let total = if anim.flipflop { num_frames * 2 } else { num_frames };
+            if f >= total {
+                f -= total;
+                if loop_frames != 0 {
+                    f %= loop_frames;
+                    f += num_frames - loop_frames;
+                } else {
+                    f = total - 1;
+                    // Stuck at the end, so another animation can start at once.
+                    self.frame_time = time;
+                }
+            }
+            let first = anim.first_frame as i32;
+            self.frame = if anim.reversed {
+                first + num_frames - 1 - f
+            } else if anim.flipflop && f >= num_frames {
+                first + num_frames - 1 - f % num_frames
+            } else {
+                first + f
+            };
+            if time > self.frame_time {
+                self.frame_time = time;
+            }
+        }
+
+        if self.frame_time > time + 200 {
+            self.frame_time = time;
+        }
+        if self.old_frame_time > time {
+            self.old_frame_time = time;
+        }
+        self.backlerp = if self.frame_time == self.old_frame_time {
+            0.0
+        } else {
+            1.0 -
+            (time - self.old_frame_time) as f32 / (self.frame_time - self.old_frame_time) as f32
+        };
+        self.blend()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {FrameBlend, Vec3};
+    use super::{Animation, AnimationConfig, AnimationErrorKind, AnimationId, AnimationPlayer,
+                Footsteps, Sex};
+
+    // The start of a stock player's file, which stops after `LEGS_TURN`.
+    const CONFIG: &str = "\
+sex m
+headoffset 0 0 0
+footsteps boot
+
+0\t30\t0\t25\t\t// BOTH_DEATH1
+29\t1\t0\t25\t\t// BOTH_DEAD1
+30\t30\t0\t25\t\t// BOTH_DEATH2
+59\t1\t0\t25\t\t// BOTH_DEAD2
+60\t30\t0\t25\t\t// BOTH_DEATH3
+89\t1\t0\t25\t\t// BOTH_DEAD3
+90\t40\t0\t20\t\t// TORSO_GESTURE
+130\t6\t0\t15\t\t// TORSO_ATTACK
+136\t6\t0\t15\t\t// TORSO_ATTACK2
+142\t5\t0\t20\t\t// TORSO_DROP
+147\t4\t0\t20\t\t// TORSO_RAISE
+151\t1\t0\t15\t\t// TORSO_STAND
+152\t1\t0\t15\t\t// TORSO_STAND2
+153\t8\t8\t20\t\t// LEGS_WALKCR
+161\t12\t12\t20\t\t// LEGS_WALK
+173\t9\t9\t18\t\t// LEGS_RUN
+182\t-10\t10\t20\t\t// LEGS_BACK
+192\t10\t10\t15\t\t// LEGS_SWIM
+202\t8\t0\t15\t\t// LEGS_JUMP
+210\t1\t0\t15\t\t// LEGS_LAND
+211\t8\t0\t15\t\t// LEGS_JUMPB
+219\t1\t0\t15\t\t// LEGS_LANDB
+220\t10\t10\t15\t\t// LEGS_IDLE
+230\t10\t10\t15\t\t// LEGS_IDLECR
+240\t7\t7\t15\t\t// LEGS_TURN
+";
+
+    fn animation(first_frame: usize,
+                 num_frames: usize,
+                 loop_frames: usize,
+                 frame_lerp: i32,
+                 reversed: bool)
+                 -> Animation {
+        Animation {
+            first_frame,
+            num_frames,
+            loop_frames,
+            frame_lerp,
+            initial_lerp: frame_lerp,
+            reversed,
+            flipflop: false,
+        }
+    }
+
+    #[test]
+    fn parses_keywords_and_animations() {
+        let config = AnimationConfig::parse(CONFIG).unwrap();
+        assert_eq!(config.sex, Sex::Male);
+        assert_eq!(config.footsteps, Footsteps::Boot);
+        assert_eq!(config.head_offset, Vec3::ZERO);
+        assert_eq!(config[AnimationId::BothDead1], animation(29, 1, 0, 40, false));
+        assert_eq!(config[AnimationId::TorsoGesture], animation(90, 40, 0, 50, false));
+        assert_eq!(config[AnimationId::TorsoStand2], animation(152, 1, 0, 66, false));
+    }
+
+    #[test]
+    fn legs_skip_the_torso_only_frames() {
+        // LEGS_WALKCR starts at 153 and TORSO_GESTURE at 90, so every legs
+        // animation moves back 63 frames.
+        let config = AnimationConfig::parse(CONFIG).unwrap();
+        assert_eq!(config[AnimationId::LegsWalkCr], animation(90, 8, 8, 50, false));
+        assert_eq!(config[AnimationId::LegsRun], animation(110, 9, 9, 55, false));
+        assert_eq!(config[AnimationId::LegsBack], animation(119, 10, 10, 50, true));
+        assert_eq!(config[AnimationId::LegsTurn], animation(177, 7, 7, 66, false));
+    }
+
+    #[test]
+    fn derives_the_remaining_animations() {
+        let config = AnimationConfig::parse(CONFIG).unwrap();
+        let gesture = config[AnimationId::TorsoGesture];
+        for &id in &[AnimationId::TorsoGetFlag,
+                     AnimationId::TorsoGuardBase,
+                     AnimationId::TorsoPatrol,
+                     AnimationId::TorsoFollowMe,
+                     AnimationId::TorsoAffirmative,
+                     AnimationId::TorsoNegative] {
+            assert_eq!(config[id], gesture);
+        }
+        assert_eq!(config[AnimationId::LegsBackCr], animation(90, 8, 8, 50, true));
+        assert_eq!(config[AnimationId::LegsBackWalk], animation(98, 12, 12, 50, true));
+        assert_eq!(config[AnimationId::FlagRun], animation(0, 16, 16, 66, false));
+        assert_eq!(config[AnimationId::FlagStand], animation(16, 5, 0, 50, false));
+        assert_eq!(config[AnimationId::FlagStand2Run], animation(16, 5, 1, 66, true));
+    }
+
+    #[test]
+    fn negative_loop_count_has_its_own_error() {
+        let text = CONFIG.replace("29\t1\t0\t25", "29\t1\t-1\t25");
+        let err = AnimationConfig::parse(&text).unwrap_err();
+        assert_eq!(err.kind, AnimationErrorKind::NegativeLoopFrames(-1));
+        assert_eq!((err.line, err.column), (6, 6));
+    }
+
+    /// Runs `player` to each time and checks the old frame, frame and
+    /// backlerp it returns.
+    fn check(player: &mut AnimationPlayer, speed_scale: f32, steps: &[(i32, usize, usize, f32)]) {
+        for &(time, old_frame, frame, backlerp) in steps {
+            let blend: FrameBlend = player.run(time, speed_scale);
+            assert_eq!((blend.old_frame, blend.frame), (old_frame, frame), "at {}", time);
+            assert!((blend.backlerp - backlerp).abs() < 1e-6,
+                    "at {}: {} != {}",
+                    time,
+                    blend.backlerp,
+                    backlerp);
+        }
+    }
+
+    #[test]
+    fn loop_wraps_to_the_loop_frames() {
+        // Four frames from 10, the last two of which loop, 100ms apart.
+        let mut player = AnimationPlayer::new(animation(10, 4, 2, 100, false), 0);
+        check(&mut player,
+              1.0,
+              &[(0, 10, 10, 1.0),
+                (50, 10, 10, 0.5),
+                (100, 10, 11, 1.0),
+                (200, 11, 12, 1.0),
+                (300, 12, 13, 1.0),
+                (350, 12, 13, 0.5),
+                (400, 13, 12, 1.0),
+                (500, 12, 13, 1.0),
+                (600, 13, 12, 1.0)]);
+    }
+
+    #[test]
+    fn one_shot_holds_the_last_frame() {
+        let mut player = AnimationPlayer::new(animation(0, 3, 0, 100, false), 0);
+        check(&mut player,
+              1.0,
+              &[(0, 0, 0, 1.0),
+                (100, 0, 1, 1.0),
+                (200, 1, 2, 1.0),
+                (250, 1, 2, 0.5),
+                (300, 2, 2, 0.0),
+                (350, 2, 2, 0.0),
+                (1000, 2, 2, 0.0)]);
+    }
+
+    #[test]
+    fn reversed_plays_backwards() {
+        let mut player = AnimationPlayer::new(animation(10, 4, 0, 100, true), 0);
+        check(&mut player,
+              1.0,
+              &[(0, 10, 13, 1.0),
+                (100, 13, 12, 1.0),
+                (200, 12, 11, 1.0),
+                (300, 11, 10, 1.0),
+                (400, 10, 10, 0.0)]);
+    }
+
+    #[test]
+    fn speed_scale_skips_frames() {
+        let mut player = AnimationPlayer::new(animation(10, 4, 4, 100, false), 0);
+        check(&mut player,
+              2.0,
+              &[(0, 10, 10, 1.0), (100, 10, 12, 1.0), (150, 10, 12, 0.5), (200, 12, 10, 1.0)]);
+    }
+
+    #[test]
+    fn set_animation_blends_in_over_initial_lerp() {
+        let mut player = AnimationPlayer::new(animation(10, 4, 4, 100, false), 0);
+        check(&mut player, 1.0, &[(0, 10, 10, 1.0), (100, 10, 11, 1.0)]);
+        // The new animation starts 40ms after the pending frame at 200.
+        player.set_animation(Animation {
+            initial_lerp: 40,
+            ..animation(20, 5, 0, 50, false)
+        });
+        check(&mut player,
+              1.0,
+              &[(160, 10, 11, 0.4),
+                (200, 11, 20, 1.0),
+                (220, 11, 20, 0.5),
+                (240, 20, 21, 1.0),
+                (250, 20, 21, 0.8),
+                (290, 21, 22, 1.0)]);
+    }
+
+    #[test]
+    fn time_jump_advances_one_frame_then_catches_up() {
+        // As in the game, a long gap moves on by one frame and shows it at
+        // once; the next call finds the frame for the current time.
+        let mut player = AnimationPlayer::new(animation(10, 4, 4, 100, false), 0);
+        check(&mut player,
+              1.0,
+              &[(0, 10, 10, 1.0), (1000, 10, 11, 0.0), (1050, 11, 12, 0.5), (1100, 12, 13, 1.0)]);
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+
+use {FrameBounds, Md3, Vec3};
+
+/// What a frame's bounds have to contain.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum BoundsSource {
+    /// The vertices of every surface, as q3data computes bounds.
+    Vertices,
+    /// The vertices and the tag origins, so that attached models are not
+    /// culled when the tags reach past the mesh.
+    VerticesAndTags,
+}
+
+/// A frame whose stored bounds do not contain its geometry.
+///
+/// Each error is how far the geometry reaches past the stored value, and is
+/// zero where the stored value is large enough.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct BoundsMismatch {
+    pub frame: usize,
+    /// How far below `min_bounds` the geometry reaches on each axis.
+    pub min_error: Vec3,
+    /// How far above `max_bounds` the geometry reaches on each axis.
+    pub max_error: Vec3,
+    /// How far past `radius` the geometry reaches from `local_origin`.
+    pub radius_error: f32,
+}
+
+impl BoundsMismatch {
+    /// The largest of the errors.
+    pub fn largest_error(&self) -> f32 {
+        let (lo, hi) = (self.min_error, self.max_error);
+        [lo.x, lo.y, lo.z, hi.x, hi.y, hi.z].iter().fold(self.radius_error, |m, &e| m.max(e))
+    }
+}
+
+impl fmt::Display for BoundsMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "frame {} geometry reaches {} units outside its bounds",
+               self.frame,
+               self.largest_error())
+    }
+}
+
+impl Md3 {
+    fn frame_points<'a>(&'a self,
+                        frame: usize,
+                        source: BoundsSource)
+                        -> Box<dyn Iterator<Item = Vec3> + 'a> {
+        let vertices = self.surfaces.iter().flat_map(move |s| s.positions(frame));
+        match (source, self.frame_tags(frame)) {
+            (BoundsSource::VerticesAndTags, Some(tags)) => {
+                Box::new(vertices.chain(tags.iter().map(|tag| tag.origin)))
+            }
+            _ => Box::new(vertices),
+        }
+    }
+
+    /// The bounds `recompute_frame_bounds` gives `frame`, or `None` if the
+    /// frame has no geometry.
+    ///
+    /// The box fits the geometry, the origin is zero and the radius reaches
+    /// the farthest corner of the box, as q3data writes them.
+    pub fn geometry_bounds(&self, frame: usize, source: BoundsSource) -> Option<FrameBounds> {
+        let mut bounds: Option<(Vec3, Vec3)> = None;
+        for p in self.frame_points(frame, source) {
+            bounds = Some(match bounds {
+                None => (p, p),
+                Some((min, max)) => {
+                    (Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                     Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+                }
+            });
+        }
+        bounds.map(|(min, max)| {
+            let corner = Vec3::new(min.x.abs().max(max.x.abs()),
+                                   min.y.abs().max(max.y.abs()),
+                                   min.z.abs().max(max.z.abs()));
+            FrameBounds {
+                min_bounds: min,
+                max_bounds: max,
+                local_origin: Vec3::ZERO,
+                radius: corner.length(),
+            }
+        })
+    }
+
+    /// Rebuilds every frame's bounds, origin and radius from its geometry.
+    /// A frame with no geometry gets zero bounds.
+    pub fn recompute_frame_bounds(&mut self, source: BoundsSource) {
+        for i in 0..self.frames.len() {
+            let bounds = self.geometry_bounds(i, source).unwrap_or(FrameBounds {
+                min_bounds: Vec3::ZERO,
+                max_bounds: Vec3::ZERO,
+                local_origin: Vec3::ZERO,
+                radius: 0.0,
+            });
+            let frame = &mut self.frames[i];
+            frame.min_bounds = bounds.min_bounds;
+            frame.max_bounds = bounds.max_bounds;
+            frame.local_origin = bounds.local_origin;
+            frame.radius = bounds.radius;
+        }
+    }
+
+    /// The frames whose stored box or sphere does not contain their
+    /// geometry. Stored bounds larger than needed are not reported.
+    pub fn verify_frame_bounds(&self, source: BoundsSource) -> Vec<BoundsMismatch> {
+        let mut mismatches = Vec::new();
+        for (i, frame) in self.frames.iter().enumerate() {
+            let mut mismatch = BoundsMismatch {
+                frame: i,
+                min_error: Vec3::ZERO,
+                max_error: Vec3::ZERO,
+                radius_error: 0.0,
+            };
+            let (lo, hi) = (frame.min_bounds, frame.max_bounds);
+            for p in self.frame_points(i, source) {
+                let min_error = &mut mismatch.min_error;
+                min_error.x = min_error.x.max(lo.x - p.x);
+                min_error.y = min_error.y.max(lo.y - p.y);
+                min_error.z = min_error.z.max(lo.z - p.z);
+                let max_error = &mut mismatch.max_error;
+                max_error.x = max_error.x.max(p.x - hi.x);
+                max_error.y = max_error.y.max(p.y - hi.y);
+                max_error.z = max_error.z.max(p.z - hi.z);
+                let reach = (p - frame.local_origin).length() - frame.radius;
+                mismatch.radius_error = mismatch.radius_error.max(reach);
+            }
+            if mismatch.largest_error() > 0.0 {
+                mismatches.push(mismatch);
+            }
+        }
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Vec3;
+    use super::BoundsSource;
+    use test_model;
+
+    #[test]
+    fn corrupt_bounds_are_reported_and_recomputed() {
+        let mut md3 = test_model::model();
+        assert!(md3.verify_frame_bounds(BoundsSource::Vertices).is_empty());
+
+        md3.frames[1].min_bounds = Vec3::new(1.0, 0.0, 0.0);
+        md3.frames[1].radius = 16.0;
+        let mismatches = md3.verify_frame_bounds(BoundsSource::Vertices);
+        assert_eq!(mismatches.len(), 1);
+        let mismatch = mismatches[0];
+        assert_eq!(mismatch.frame, 1);
+        assert_eq!(mismatch.min_error, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(mismatch.max_error, Vec3::ZERO);
+        // The farthest vertex, (0, 16, 8), is inside the corner radius of 24.
+        let reach = 320f32.sqrt() - 16.0;
+        assert!((mismatch.radius_error - reach).abs() < 1e-5);
+        assert_eq!(mismatch.largest_error(), mismatch.radius_error);
+
+        md3.recompute_frame_bounds(BoundsSource::Vertices);
+        assert!(md3.verify_frame_bounds(BoundsSource::Vertices).is_empty());
+        assert_eq!(format!("{:?}", md3), format!("{:?}", test_model::model()));
+    }
+
+    #[test]
+    fn tags_reach_past_the_vertices() {
+        let mut md3 = test_model::model();
+        let vertices = md3.geometry_bounds(0, BoundsSource::Vertices).unwrap();
+        assert_eq!(vertices.min_bounds, Vec3::ZERO);
+        assert_eq!(vertices.max_bounds, Vec3::new(8.0, 8.0, 4.0));
+        assert_eq!(vertices.radius, 12.0);
+        let tagged = md3.geometry_bounds(0, BoundsSource::VerticesAndTags).unwrap();
+        assert_eq!(tagged.max_bounds, Vec3::new(8.0, 8.0, 10.0));
+        assert_eq!(tagged.radius, 228f32.sqrt());
+
+        let mismatches = md3.verify_frame_bounds(BoundsSource::VerticesAndTags);
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].frame, 0);
+        assert_eq!(mismatches[0].max_error, Vec3::new(0.0, 0.0, 6.0));
+        assert_eq!(mismatches[0].radius_error, 0.0);
+        assert_eq!(mismatches[1].frame, 1);
+        assert_eq!(mismatches[1].max_error, Vec3::new(0.0, 0.0, 3.0));
+
+        md3.recompute_frame_bounds(BoundsSource::VerticesAndTags);
+        assert!(md3.verify_frame_bounds(BoundsSource::VerticesAndTags).is_empty());
+        assert!(md3.verify_frame_bounds(BoundsSource::Vertices).is_empty());
+        assert_eq!(md3.frames[0].max_bounds, Vec3::new(8.0, 8.0, 10.0));
+        assert_eq!(md3.frames[1].max_bounds, Vec3::new(16.0, 16.0, 11.0));
+    }
+
+    #[test]
+    fn frames_without_geometry() {
+        let mut md3 = test_model::model();
+        md3.surfaces.clear();
+        md3.tags.clear();
+        assert!(md3.geometry_bounds(0, BoundsSource::VerticesAndTags).is_none());
+        md3.recompute_frame_bounds(BoundsSource::VerticesAndTags);
+        assert_eq!(md3.frames[1].max_bounds, Vec3::ZERO);
+        assert_eq!(md3.frames[1].radius, 0.0);
+    }
+}
@@ -0,0 +1,524 @@
+use bytemuck;
+
+use {Surface, Vertex};
+use vertex::decode_normal;
+
+/// How positions are stored in a vertex buffer.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum PositionFormat {
+    /// Three `f32`, 12 bytes.
+    Float32,
+    /// Three `f16` and a zero pad, 8 bytes.
+    Float16,
+    /// The quantized `i16` coordinates as stored in the file and a zero pad,
+    /// 8 bytes. Read as snorm, they are scaled by `SNORM16_POSITION_SCALE`.
+    Snorm16,
+}
+
+/// Multiplies a `PositionFormat::Snorm16` position, as the GPU normalizes
+/// it, back into model units.
+pub const SNORM16_POSITION_SCALE: f32 = 32767.0 / 64.0;
+
+/// How normals are stored in a vertex buffer. Normals are decoded through
+/// the renderer's sine table.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum NormalFormat {
+    /// Three `f32`, 12 bytes.
+    Float32,
+    /// Three `f16` and a zero pad, 8 bytes.
+    Float16,
+    /// Three snorm `i16` and a zero pad, 8 bytes.
+    Snorm16,
+    /// Three snorm `i8` and a zero pad, 4 bytes.
+    Snorm8,
+}
+
+/// How texture coordinates are stored in a vertex buffer.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum TexCoordFormat {
+    /// Two `f32`, 8 bytes.
+    Float32,
+    /// Two `f16`, 4 bytes.
+    Float16,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Attribute {
+    Position,
+    Normal,
+    TexCoord,
+}
+
+/// Whether attributes are interleaved per vertex or kept in separate arrays.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Arrangement {
+    /// Position, normal and texture coordinate of each vertex together.
+    Interleaved,
+    /// All positions, then all normals, then all texture coordinates.
+    Planar,
+}
+
+/// What `Surface::build_buffers` writes and how. Attributes set to `None`
+/// are left out.
+///
+/// Every attribute takes a multiple of 4 bytes, so every offset and stride
+/// is too.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct VertexLayout {
+    pub arrangement: Arrangement,
+    pub position: Option<PositionFormat>,
+    pub normal: Option<NormalFormat>,
+    pub tex_coord: Option<TexCoordFormat>,
+}
+
+impl Default for VertexLayout {
+    /// Interleaved `f32` positions, normals and texture coordinates.
+    fn default() -> VertexLayout {
+        VertexLayout {
+            arrangement: Arrangement::Interleaved,
+            position: Some(PositionFormat::Float32),
+            normal: Some(NormalFormat::Float32),
+            tex_coord: Some(TexCoordFormat::Float32),
+        }
+    }
+}
+
+impl VertexLayout {
+    /// Bytes one vertex's `attribute` takes, or 0 if it is left out.
+    pub fn size(&self, attribute: Attribute) -> usize {
+        match attribute {
+            Attribute::Position => {
+                match self.position {
+                    Some(PositionFormat::Float32) => 12,
+                    Some(PositionFormat::Float16) | Some(PositionFormat::Snorm16) => 8,
+                    None => 0,
+                }
+            }
+            Attribute::Normal => {
+                match self.normal {
+                    Some(NormalFormat::Float32) => 12,
+                    Some(NormalFormat::Float16) | Some(NormalFormat::Snorm16) => 8,
+                    Some(NormalFormat::Snorm8) => 4,
+                    None => 0,
+                }
+            }
+            Attribute::TexCoord => {
+                match self.tex_coord {
+                    Some(TexCoordFormat::Float32) => 8,
+                    Some(TexCoordFormat::Float16) => 4,
+                    None => 0,
+                }
+            }
+        }
+    }
+
+    /// Bytes of all attributes of one vertex.
+    pub fn vertex_size(&self) -> usize {
+        self.size(Attribute::Position) + self.size(Attribute::Normal) +
+        self.size(Attribute::TexCoord)
+    }
+
+    /// Bytes of the attributes that come before `attribute`.
+    fn preceding(&self, attribute: Attribute) -> usize {
+        match attribute {
+            Attribute::Position => 0,
+            Attribute::Normal => self.size(Attribute::Position),
+            Attribute::TexCoord => self.size(Attribute::Position) + self.size(Attribute::Normal),
+        }
+    }
+}
+
+/// Vertex data packed for upload, as built by `Surface::build_buffers` and
+/// `Surface::build_morph_buffers`.
+///
+/// The data is kept in native-endian `u32` words so it can be cast with
+/// `bytemuck` to bytes, or to `f32` when every attribute is `Float32`.
+/// Frames follow one another, each `frame_size` bytes and laid out alike.
+#[derive(Debug,Clone,PartialEq)]
+pub struct VertexBuffers {
+    pub layout: VertexLayout,
+    pub vertex_count: usize,
+    pub frame_count: usize,
+    pub data: Vec<u32>,
+}
+
+impl VertexBuffers {
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.data)
+    }
+
+    /// Bytes of one frame.
+    pub fn frame_size(&self) -> usize {
+        self.vertex_count * self.layout.vertex_size()
+    }
+
+    /// Byte offset of the first `attribute` of `frame`.
+    pub fn offset(&self, frame: usize, attribute: Attribute) -> usize {
+        let start = frame * self.frame_size();
+        match self.layout.arrangement {
+            Arrangement::Interleaved => start + self.layout.preceding(attribute),
+            Arrangement::Planar => start + self.layout.preceding(attribute) * self.vertex_count,
+        }
+    }
+
+    /// Bytes from one vertex's `attribute` to the next: the vertex size when
+    /// interleaved, the attribute size when planar.
+    pub fn stride(&self, attribute: Attribute) -> usize {
+        match self.layout.arrangement {
+            Arrangement::Interleaved => self.layout.vertex_size(),
+            Arrangement::Planar => self.layout.size(attribute),
+        }
+    }
+}
+
+/// Converts to IEEE half precision, rounding to nearest even.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinity; NaN stays quiet NaN.
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let (mantissa, shift) = if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal: the implicit bit becomes part of the mantissa.
+        (mantissa | 0x80_0000, (14 - exponent) as u32)
+    } else {
+        (mantissa, 13)
+    };
+    let half = (exponent.max(0) as u32) << 10 | mantissa >> shift;
+    let rest = mantissa & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    // A carry out of the mantissa moves correctly into the exponent.
+    let round = rest > halfway || (rest == halfway && half & 1 == 1);
+    sign | (half + round as u32) as u16
+}
+
+fn snorm16(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * 32767.0).round() as i16
+}
+
+fn snorm8(value: f32) -> i8 {
+    (value.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+/// Writes `values` to the start of `out`, native-endian. The rest of `out`
+/// is padding and is left zero.
+fn put_f32(out: &mut [u8], values: &[f32]) {
+    for (chunk, value) in out.chunks_mut(4).zip(values) {
+        chunk.copy_from_slice(&value.to_ne_bytes());
+    }
+}
+
+fn put_f16(out: &mut [u8], values: &[f32]) {
+    for (chunk, value) in out.chunks_mut(2).zip(values) {
+        chunk.copy_from_slice(&f32_to_f16(*value).to_ne_bytes());
+    }
+}
+
+fn put_i16(out: &mut [u8], values: &[i16]) {
+    for (chunk, value) in out.chunks_mut(2).zip(values) {
+        chunk.copy_from_slice(&value.to_ne_bytes());
+    }
+}
+
+fn put_attribute(out: &mut [u8],
+                 layout: &VertexLayout,
+                 attribute: Attribute,
+                 vertex: &Vertex,
+                 st: [f32; 2]) {
+    match attribute {
+        Attribute::Position => {
+            match layout.position {
+                Some(PositionFormat::Float32) => put_f32(out, &vertex.position().to_array()),
+                Some(PositionFormat::Float16) => put_f16(out, &vertex.position().to_array()),
+                Some(PositionFormat::Snorm16) => put_i16(out, &[vertex.x, vertex.y, vertex.z]),
+                None => {}
+            }
+        }
+        Attribute::Normal => {
+            let normal = || decode_normal(vertex.normal).to_array();
+            match layout.normal {
+                Some(NormalFormat::Float32) => put_f32(out, &normal()),
+                Some(NormalFormat::Float16) => put_f16(out, &normal()),
+                Some(NormalFormat::Snorm16) => {
+                    let n = normal();
+                    put_i16(out, &[snorm16(n[0]), snorm16(n[1]), snorm16(n[2])])
+                }
+                Some(NormalFormat::Snorm8) => {
+                    for (byte, n) in out.iter_mut().zip(&normal()) {
+                        *byte = snorm8(*n) as u8;
+                    }
+                }
+                None => {}
+            }
+        }
+        Attribute::TexCoord => {
+            match layout.tex_coord {
+                Some(TexCoordFormat::Float32) => put_f32(out, &st),
+                Some(TexCoordFormat::Float16) => put_f16(out, &st),
+                None => {}
+            }
+        }
+    }
+}
+
+const ATTRIBUTES: [Attribute; 3] = [Attribute::Position, Attribute::Normal, Attribute::TexCoord];
+
+impl Surface {
+    /// Packs the vertices of `frame` for upload, or returns `None` if the
+    /// frame does not exist.
+    pub fn build_buffers(&self, frame: usize, layout: VertexLayout) -> Option<VertexBuffers> {
+        self.vertices.get(frame)?;
+        Some(self.pack(frame..frame + 1, layout))
+    }
+
+    /// Packs every frame into one buffer, frame after frame, for morphing on
+    /// the GPU. Leave `tex_coord` out of the layout to avoid repeating the
+    /// texture coordinates in every frame.
+    pub fn build_morph_buffers(&self, layout: VertexLayout) -> VertexBuffers {
+        self.pack(0..self.vertices.len(), layout)
+    }
+
+    fn pack(&self, frames: ::std::ops::Range<usize>, layout: VertexLayout) -> VertexBuffers {
+        let vertex_count = self.vertices.get(frames.start).map_or(0, |v| v.len());
+        let mut buffers = VertexBuffers {
+            layout,
+            vertex_count,
+            frame_count: frames.len(),
+            data: Vec::new(),
+        };
+        buffers.data = vec![0; buffers.frame_size() * frames.len() / 4];
+        let offsets: Vec<_> = ATTRIBUTES.iter()
+            .map(|&a| (a, buffers.offset(0, a), buffers.stride(a), layout.size(a)))
+            .collect();
+        let frame_size = buffers.frame_size();
+
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut buffers.data);
+        for (i, frame) in frames.enumerate() {
+            let vertices = &self.vertices[frame];
+            for (j, vertex) in vertices.iter().enumerate().take(vertex_count) {
+                let st = self.tex_coords.get(j).map_or([0.0, 0.0], |t| t.st);
+                for &(attribute, offset, stride, size) in &offsets {
+                    let start = i * frame_size + offset + j * stride;
+                    put_attribute(&mut bytes[start..start + size], &layout, attribute, vertex, st);
+                }
+            }
+        }
+        buffers
+    }
+
+    /// The triangle indexes as stored, three per triangle, or `None` if an
+    /// index does not fit in 16 bits.
+    pub fn index_buffer_u16(&self) -> Option<Vec<u16>> {
+        let mut indexes = Vec::with_capacity(self.triangles.len() * 3);
+        for index in self.triangles.iter().flat_map(|t| t.indexes.iter()) {
+            if *index < 0 || *index > u16::MAX as i32 {
+                return None;
+            }
+            indexes.push(*index as u16);
+        }
+        Some(indexes)
+    }
+
+    /// The triangle indexes as stored, three per triangle, or `None` if an
+    /// index is negative.
+    pub fn index_buffer_u32(&self) -> Option<Vec<u32>> {
+        let mut indexes = Vec::with_capacity(self.triangles.len() * 3);
+        for index in self.triangles.iter().flat_map(|t| t.indexes.iter()) {
+            if *index < 0 {
+                return None;
+            }
+            indexes.push(*index as u32);
+        }
+        Some(indexes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck;
+
+    use test_model;
+    use vertex::decode_normal;
+    use super::{f32_to_f16, Arrangement, Attribute, NormalFormat, PositionFormat, TexCoordFormat,
+                VertexBuffers, VertexLayout};
+
+    const TEX_COORDS: [[f32; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+
+    fn buffers(layout: VertexLayout, vertex_count: usize) -> VertexBuffers {
+        VertexBuffers {
+            layout,
+            vertex_count,
+            frame_count: 1,
+            data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn half_precision() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(1.0 / 3.0), 0x3555);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        // Smallest normal and subnormals, with ties rounding to even.
+        assert_eq!(f32_to_f16(2f32.powi(-14)), 0x0400);
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(-2f32.powi(-24)), 0x8001);
+        assert_eq!(f32_to_f16(2f32.powi(-25)), 0x0000);
+        assert_eq!(f32_to_f16(3.0 * 2f32.powi(-25)), 0x0002);
+        assert_eq!(f32_to_f16(2f32.powi(-30)), 0x0000);
+        // Overflow, including rounding up past the largest half.
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+        assert_eq!(f32_to_f16(-1.0e6), 0xfc00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7fff, 0x7e00);
+    }
+
+    #[test]
+    fn layout_offsets_and_strides() {
+        let layouts = [(Some(PositionFormat::Float32),
+                        Some(NormalFormat::Float32),
+                        Some(TexCoordFormat::Float32),
+                        [12, 12, 8]),
+                       (Some(PositionFormat::Float16),
+                        Some(NormalFormat::Float16),
+                        Some(TexCoordFormat::Float16),
+                        [8, 8, 4]),
+                       (Some(PositionFormat::Snorm16),
+                        Some(NormalFormat::Snorm16),
+                        None,
+                        [8, 8, 0]),
+                       (None,
+                        Some(NormalFormat::Snorm8),
+                        Some(TexCoordFormat::Float32),
+                        [0, 4, 8])];
+        for &(position, normal, tex_coord, sizes) in &layouts {
+            let mut layout = VertexLayout {
+                arrangement: Arrangement::Interleaved,
+                position,
+                normal,
+                tex_coord,
+            };
+            let vertex_size = sizes[0] + sizes[1] + sizes[2];
+            assert_eq!(layout.vertex_size(), vertex_size);
+
+            let interleaved = buffers(layout, 3);
+            assert_eq!(interleaved.frame_size(), 3 * vertex_size);
+            for (i, &attribute) in super::ATTRIBUTES.iter().enumerate() {
+                let preceding: usize = sizes[..i].iter().sum();
+                assert_eq!(layout.size(attribute), sizes[i]);
+                assert_eq!(interleaved.offset(0, attribute), preceding);
+                assert_eq!(interleaved.offset(2, attribute), 6 * vertex_size + preceding);
+                assert_eq!(interleaved.stride(attribute), vertex_size);
+            }
+
+            layout.arrangement = Arrangement::Planar;
+            let planar = buffers(layout, 3);
+            for (i, &attribute) in super::ATTRIBUTES.iter().enumerate() {
+                let preceding: usize = sizes[..i].iter().sum();
+                assert_eq!(planar.offset(0, attribute), 3 * preceding);
+                assert_eq!(planar.offset(2, attribute), 6 * vertex_size + 3 * preceding);
+                assert_eq!(planar.stride(attribute), sizes[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn interleaved_float32_contents() {
+        let model = test_model::model();
+        let surface = &model.surfaces[0];
+        assert!(surface.build_buffers(2, VertexLayout::default()).is_none());
+
+        let buffers = surface.build_buffers(1, VertexLayout::default()).unwrap();
+        assert_eq!((buffers.vertex_count, buffers.frame_count), (3, 1));
+        let normal = decode_normal(surface.vertices[1][0].normal).to_array();
+        let positions = [[0.0, 0.0, 0.0], [16.0, 0.0, 0.0], [0.0, 16.0, 8.0]];
+        let mut expected = Vec::new();
+        for (position, st) in positions.iter().zip(&TEX_COORDS) {
+            expected.extend_from_slice(position);
+            expected.extend_from_slice(&normal);
+            expected.extend_from_slice(st);
+        }
+        let floats: &[f32] = bytemuck::cast_slice(&buffers.data);
+        assert_eq!(floats, &expected[..]);
+        assert_eq!(buffers.as_bytes().len(), 3 * 32);
+    }
+
+    #[test]
+    fn planar_packed_contents() {
+        let model = test_model::model();
+        let surface = &model.surfaces[0];
+        let layout = VertexLayout {
+            arrangement: Arrangement::Planar,
+            position: Some(PositionFormat::Snorm16),
+            normal: Some(NormalFormat::Snorm8),
+            tex_coord: Some(TexCoordFormat::Float16),
+        };
+        let buffers = surface.build_buffers(1, layout).unwrap();
+        let normal = decode_normal(surface.vertices[1][0].normal).to_array();
+        let normal: Vec<u8> = normal.iter().map(|n| (n * 127.0).round() as i8 as u8).collect();
+
+        let mut expected = Vec::new();
+        for &(x, y, z) in &[(0i16, 0i16, 0i16), (1024, 0, 0), (0, 1024, 512)] {
+            for value in &[x, y, z, 0] {
+                expected.extend_from_slice(&value.to_ne_bytes());
+            }
+        }
+        for _ in 0..3 {
+            expected.extend_from_slice(&normal);
+            expected.push(0);
+        }
+        for st in &TEX_COORDS {
+            expected.extend_from_slice(&f32_to_f16(st[0]).to_ne_bytes());
+            expected.extend_from_slice(&f32_to_f16(st[1]).to_ne_bytes());
+        }
+        assert_eq!(buffers.as_bytes(), &expected[..]);
+    }
+
+    #[test]
+    fn morph_buffers_follow_frame_after_frame() {
+        let model = test_model::model();
+        let surface = &model.surfaces[0];
+        let layout = VertexLayout { tex_coord: None, ..VertexLayout::default() };
+        let morph = surface.build_morph_buffers(layout);
+        assert_eq!((morph.vertex_count, morph.frame_count), (3, 2));
+        assert_eq!(morph.offset(1, Attribute::Position), morph.frame_size());
+
+        let mut expected = surface.build_buffers(0, layout).unwrap().data;
+        expected.extend(surface.build_buffers(1, layout).unwrap().data);
+        assert_eq!(morph.data, expected);
+
+        let floats: &[f32] = bytemuck::cast_slice(&morph.data);
+        let at = morph.offset(1, Attribute::Position) / 4 + morph.stride(Attribute::Position) / 4;
+        assert_eq!(&floats[at..at + 3], &[16.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn index_buffers() {
+        let mut model = test_model::model();
+        let surface = &mut model.surfaces[0];
+        assert_eq!(surface.index_buffer_u16(), Some(vec![0, 1, 2]));
+        assert_eq!(surface.index_buffer_u32(), Some(vec![0, 1, 2]));
+
+        surface.triangles[0].indexes[2] = 70000;
+        assert_eq!(surface.index_buffer_u16(), None);
+        assert_eq!(surface.index_buffer_u32(), Some(vec![0, 1, 70000]));
+
+        surface.triangles[0].indexes[2] = -1;
+        assert_eq!(surface.index_buffer_u16(), None);
+        assert_eq!(surface.index_buffer_u32(), None);
+    }
+}
@@ -0,0 +1,543 @@
+use std::error;
+use std::fmt;
+
+use {BoundsSource, Frame, FrameName, Mat3, Md3, Md3Error, Md3Header, NameError, QPath, Shader,
+     Surface, SurfaceHeader, Tag, TexCoord, Triangle, Vec3, Vertex};
+use {MD3_MAGIC, MD3_VERSION};
+use vertex::{encode_normal, quantize_position, Quantized};
+
+/// Errors returned when builder input is inconsistent.
+#[derive(Debug)]
+pub enum BuildError {
+    Name(NameError),
+    /// The model is too large for the file format's `i32` fields.
+    Md3(Md3Error),
+    /// The model has no frames; the engine refuses to load such models.
+    NoFrames,
+    FrameOutOfRange { frame: usize },
+    FrameCountMismatch { surface: usize, expected: usize, found: usize },
+    VertexCountMismatch { surface: usize, frame: usize, expected: usize, found: usize },
+    NormalCountMismatch { surface: usize, frame: usize, expected: usize, found: usize },
+    IndexOutOfRange { surface: usize, triangle: usize, index: u32 },
+    /// A vertex lies outside the range an MD3 position can store.
+    PositionOutOfRange { surface: usize, frame: usize, vertex: usize },
+    DuplicateTag { frame: usize, name: QPath },
+    /// A tag present in one frame is missing from `frame`.
+    MissingTag { frame: usize, name: QPath },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::Name(ref err) => write!(f, "{}", err),
+            BuildError::Md3(ref err) => write!(f, "{}", err),
+            BuildError::NoFrames => write!(f, "model has no frames"),
+            BuildError::FrameOutOfRange { frame } => write!(f, "frame {} does not exist", frame),
+            BuildError::FrameCountMismatch { surface, expected, found } => {
+                write!(f,
+                       "surface {} has {} frames, model has {}",
+                       surface,
+                       found,
+                       expected)
+            }
+            BuildError::VertexCountMismatch { surface, frame, expected, found } => {
+                write!(f,
+                       "surface {} frame {} has {} vertices, expected {}",
+                       surface,
+                       frame,
+                       found,
+                       expected)
+            }
+            BuildError::NormalCountMismatch { surface, frame, expected, found } => {
+                write!(f,
+                       "surface {} frame {} has {} normals, expected {}",
+                       surface,
+                       frame,
+                       found,
+                       expected)
+            }
+            BuildError::IndexOutOfRange { surface, triangle, index } => {
+                write!(f,
+                       "surface {} triangle {} uses vertex {} which does not exist",
+                       surface,
+                       triangle,
+                       index)
+            }
+            BuildError::PositionOutOfRange { surface, frame, vertex } => {
+                write!(f,
+                       "surface {} frame {} vertex {} is out of range",
+                       surface,
+                       frame,
+                       vertex)
+            }
+            BuildError::DuplicateTag { frame, ref name } => {
+                write!(f, "tag {} appears twice in frame {}", name, frame)
+            }
+            BuildError::MissingTag { frame, ref name } => {
+                write!(f, "tag {} is missing from frame {}", name, frame)
+            }
+        }
+    }
+}
+
+impl error::Error for BuildError {}
+
+impl From<NameError> for BuildError {
+    fn from(err: NameError) -> BuildError {
+        BuildError::Name(err)
+    }
+}
+
+impl From<Md3Error> for BuildError {
+    fn from(err: Md3Error) -> BuildError {
+        BuildError::Md3(err)
+    }
+}
+
+struct BuilderTag {
+    name: QPath,
+    origin: Vec3,
+    axis: Mat3,
+}
+
+/// Assembles an `Md3` from float data, computing every count, offset,
+/// bound and quantized value.
+pub struct Md3Builder {
+    name: QPath,
+    flags: i32,
+    frames: Vec<FrameName>,
+    tags: Vec<Vec<BuilderTag>>,
+    surfaces: Vec<SurfaceBuilder>,
+}
+
+impl Md3Builder {
+    pub fn new(name: &str) -> Result<Md3Builder, BuildError> {
+        Ok(Md3Builder {
+            name: QPath::new(name)?,
+            flags: 0,
+            frames: Vec::new(),
+            tags: Vec::new(),
+            surfaces: Vec::new(),
+        })
+    }
+
+    pub fn flags(&mut self, flags: i32) -> &mut Md3Builder {
+        self.flags = flags;
+        self
+    }
+
+    /// Adds a frame and returns its index.
+    pub fn add_frame(&mut self, name: &str) -> Result<usize, BuildError> {
+        self.frames.push(FrameName::new(name)?);
+        self.tags.push(Vec::new());
+        Ok(self.frames.len() - 1)
+    }
+
+    /// Places a tag in `frame`. Every frame must end up with the same tags.
+    pub fn add_tag(&mut self,
+                   frame: usize,
+                   name: &str,
+                   origin: Vec3,
+                   axis: Mat3)
+                   -> Result<&mut Md3Builder, BuildError> {
+        let name = QPath::new(name)?;
+        let tags = match self.tags.get_mut(frame) {
+            Some(tags) => tags,
+            None => return Err(BuildError::FrameOutOfRange { frame }),
+        };
+        if tags.iter().any(|t| t.name == name) {
+            return Err(BuildError::DuplicateTag { frame, name });
+        }
+        tags.push(BuilderTag { name, origin, axis });
+        Ok(self)
+    }
+
+    pub fn add_surface(&mut self, surface: SurfaceBuilder) -> &mut Md3Builder {
+        self.surfaces.push(surface);
+        self
+    }
+
+    pub fn build(&self) -> Result<Md3, BuildError> {
+        let num_frames = self.frames.len();
+        if num_frames == 0 {
+            return Err(BuildError::NoFrames);
+        }
+
+        // Tags are stored in the order they were added to the first frame.
+        let order: Vec<QPath> = self.tags[0].iter().map(|t| t.name).collect();
+        let mut tags = Vec::with_capacity(num_frames * order.len());
+        for (frame, frame_tags) in self.tags.iter().enumerate() {
+            if let Some(extra) = frame_tags.iter().find(|t| !order.contains(&t.name)) {
+                return Err(BuildError::MissingTag {
+                    frame: 0,
+                    name: extra.name,
+                });
+            }
+            for name in &order {
+                let tag = match frame_tags.iter().find(|t| t.name == *name) {
+                    Some(tag) => tag,
+                    None => {
+                        return Err(BuildError::MissingTag {
+                            frame,
+                            name: *name,
+                        })
+                    }
+                };
+                tags.push(Tag {
+                    name: tag.name,
+                    origin: tag.origin,
+                    axis: tag.axis,
+                });
+            }
+        }
+
+        let mut surfaces = Vec::with_capacity(self.surfaces.len());
+        for (j, surface) in self.surfaces.iter().enumerate() {
+            surfaces.push(surface.build(j, num_frames)?);
+        }
+
+        let frames = self.frames
+            .iter()
+            .map(|name| {
+                Frame {
+                    min_bounds: Vec3::ZERO,
+                    max_bounds: Vec3::ZERO,
+                    local_origin: Vec3::ZERO,
+                    radius: 0.0,
+                    name: *name,
+                }
+            })
+            .collect();
+
+        let mut md3 = Md3 {
+            header: Md3Header {
+                ident: MD3_MAGIC,
+                version: MD3_VERSION,
+                name: self.name,
+                flags: self.flags,
+                num_frames: 0,
+                num_tags: 0,
+                num_surfaces: 0,
+                num_skins: 0,
+                ofs_frames: 0,
+                ofs_tags: 0,
+                ofs_surfaces: 0,
+                ofs_eof: 0,
+            },
+            frames,
+            tags,
+            surfaces,
+            padding: Vec::new(),
+        };
+        md3.recompute_frame_bounds(BoundsSource::Vertices);
+        let (header, surface_headers) = md3.canonical_headers()?;
+        md3.header = header;
+        for (surface, header) in md3.surfaces.iter_mut().zip(surface_headers) {
+            surface.header = header;
+        }
+        Ok(md3)
+    }
+}
+
+/// Collects the data of one surface for an `Md3Builder`.
+///
+/// Texture coordinates define the vertex count; every frame added must
+/// supply that many positions and normals.
+pub struct SurfaceBuilder {
+    name: QPath,
+    flags: i32,
+    shaders: Vec<QPath>,
+    tex_coords: Vec<[f32; 2]>,
+    triangles: Vec<[u32; 3]>,
+    frames: Vec<(Vec<Vec3>, Vec<Vec3>)>,
+}
+
+impl SurfaceBuilder {
+    pub fn new(name: &str) -> Result<SurfaceBuilder, BuildError> {
+        Ok(SurfaceBuilder {
+            name: QPath::new(name)?,
+            flags: 0,
+            shaders: Vec::new(),
+            tex_coords: Vec::new(),
+            triangles: Vec::new(),
+            frames: Vec::new(),
+        })
+    }
+
+    pub fn flags(&mut self, flags: i32) -> &mut SurfaceBuilder {
+        self.flags = flags;
+        self
+    }
+
+    pub fn add_shader(&mut self, name: &str) -> Result<&mut SurfaceBuilder, BuildError> {
+        self.shaders.push(QPath::new(name)?);
+        Ok(self)
+    }
+
+    pub fn tex_coords(&mut self, tex_coords: &[[f32; 2]]) -> &mut SurfaceBuilder {
+        self.tex_coords = tex_coords.to_vec();
+        self
+    }
+
+    pub fn add_triangle(&mut self, indexes: [u32; 3]) -> &mut SurfaceBuilder {
+        self.triangles.push(indexes);
+        self
+    }
+
+    /// Adds the positions and normals of the next frame.
+    pub fn add_frame(&mut self, positions: &[Vec3], normals: &[Vec3]) -> &mut SurfaceBuilder {
+        self.frames.push((positions.to_vec(), normals.to_vec()));
+        self
+    }
+
+    fn build(&self, j: usize, num_frames: usize) -> Result<Surface, BuildError> {
+        if self.frames.len() != num_frames {
+            return Err(BuildError::FrameCountMismatch {
+                surface: j,
+                expected: num_frames,
+                found: self.frames.len(),
+            });
+        }
+        let num_verts = self.tex_coords.len();
+
+        let mut triangles = Vec::with_capacity(self.triangles.len());
+        for (t, indexes) in self.triangles.iter().enumerate() {
+            if let Some(index) = indexes.iter().find(|i| **i as usize >= num_verts) {
+                return Err(BuildError::IndexOutOfRange {
+                    surface: j,
+                    triangle: t,
+                    index: *index,
+                });
+            }
+            triangles.push(Triangle {
+                indexes: [indexes[0] as i32, indexes[1] as i32, indexes[2] as i32],
+            });
+        }
+
+        let mut vertices = Vec::with_capacity(num_frames);
+        for (frame, (positions, normals)) in self.frames.iter().enumerate() {
+            if positions.len() != num_verts {
+                return Err(BuildError::VertexCountMismatch {
+                    surface: j,
+                    frame,
+                    expected: num_verts,
+                    found: positions.len(),
+                });
+            }
+            if normals.len() != num_verts {
+                return Err(BuildError::NormalCountMismatch {
+                    surface: j,
+                    frame,
+                    expected: num_verts,
+                    found: normals.len(),
+                });
+            }
+            let mut frame_vertices = Vec::with_capacity(num_verts);
+            for (vertex, (p, n)) in positions.iter().zip(normals).enumerate() {
+                let Quantized { xyz, clamped } = quantize_position(*p);
+                if clamped {
+                    return Err(BuildError::PositionOutOfRange {
+                        surface: j,
+                        frame,
+                        vertex,
+                    });
+                }
+                frame_vertices.push(Vertex {
+                    x: xyz[0],
+                    y: xyz[1],
+                    z: xyz[2],
+                    normal: encode_normal(*n),
+                });
+            }
+            vertices.push(frame_vertices);
+        }
+
+        Ok(Surface {
+            header: SurfaceHeader {
+                ident: MD3_MAGIC,
+                name: self.name,
+                flags: self.flags,
+                num_frames: 0,
+                num_shaders: 0,
+                num_verts: 0,
+                num_triangles: 0,
+                ofs_triangles: 0,
+                ofs_shaders: 0,
+                ofs_st: 0,
+                ofs_xyznormal: 0,
+                ofs_end: 0,
+            },
+            shaders: self.shaders
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    Shader {
+                        name: *name,
+                        shader_index: i as i32,
+                    }
+                })
+                .collect(),
+            triangles,
+            tex_coords: self.tex_coords.iter().map(|st| TexCoord { st: *st }).collect(),
+            vertices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Mat3, Md3, Vec3};
+    use super::{BuildError, Md3Builder, SurfaceBuilder};
+    use test_model;
+
+    const POSITIONS: [Vec3; 3] = [Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+                                  Vec3 { x: 1.0, y: 0.0, z: 0.0 },
+                                  Vec3 { x: 0.0, y: 1.0, z: 0.0 }];
+    const NORMALS: [Vec3; 3] = [Vec3 { x: 0.0, y: 0.0, z: 1.0 }; 3];
+
+    fn surface(frames: usize) -> SurfaceBuilder {
+        let mut surface = SurfaceBuilder::new("body").unwrap();
+        surface.tex_coords(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]).add_triangle([0, 1, 2]);
+        for _ in 0..frames {
+            surface.add_frame(&POSITIONS, &NORMALS);
+        }
+        surface
+    }
+
+    fn builder(frames: usize) -> Md3Builder {
+        let mut builder = Md3Builder::new("models/test.md3").unwrap();
+        for i in 0..frames {
+            builder.add_frame(&format!("frame{}", i)).unwrap();
+        }
+        builder
+    }
+
+    fn tag(builder: &mut Md3Builder, frame: usize, name: &str) {
+        builder.add_tag(frame, name, Vec3::ZERO, Mat3::IDENTITY).unwrap();
+    }
+
+    #[test]
+    fn built_model_validates_and_round_trips() {
+        let md3 = test_model::model();
+        let bytes = md3.to_bytes().unwrap();
+        assert!(Md3::validate(&bytes).is_valid());
+        let loaded = Md3::from_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", md3));
+    }
+
+    #[test]
+    fn no_frames() {
+        let mut builder = builder(0);
+        builder.add_surface(surface(0));
+        match builder.build() {
+            Err(BuildError::NoFrames) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn tag_in_missing_frame() {
+        match builder(1).add_tag(1, "tag_top", Vec3::ZERO, Mat3::IDENTITY) {
+            Err(BuildError::FrameOutOfRange { frame: 1 }) => {}
+            Err(err) => panic!("{:?}", err),
+            Ok(_) => panic!("tag added"),
+        }
+    }
+
+    #[test]
+    fn duplicate_tag() {
+        let mut builder = builder(1);
+        tag(&mut builder, 0, "tag_top");
+        match builder.add_tag(0, "tag_top", Vec3::ZERO, Mat3::IDENTITY) {
+            Err(BuildError::DuplicateTag { frame: 0, name }) => assert_eq!(name, "tag_top"),
+            Err(err) => panic!("{:?}", err),
+            Ok(_) => panic!("tag added"),
+        }
+    }
+
+    #[test]
+    fn tag_missing_from_later_frame() {
+        let mut builder = builder(2);
+        tag(&mut builder, 0, "tag_top");
+        tag(&mut builder, 0, "tag_weapon");
+        tag(&mut builder, 1, "tag_top");
+        match builder.build() {
+            Err(BuildError::MissingTag { frame: 1, name }) => assert_eq!(name, "tag_weapon"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn tag_missing_from_first_frame() {
+        let mut builder = builder(2);
+        tag(&mut builder, 0, "tag_top");
+        tag(&mut builder, 1, "tag_top");
+        tag(&mut builder, 1, "tag_extra");
+        match builder.build() {
+            Err(BuildError::MissingTag { frame: 0, name }) => assert_eq!(name, "tag_extra"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn frame_count_mismatch() {
+        let mut builder = builder(2);
+        builder.add_surface(surface(1));
+        match builder.build() {
+            Err(BuildError::FrameCountMismatch { surface: 0, expected: 2, found: 1 }) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        let mut surface = surface(1);
+        surface.add_triangle([0, 2, 3]);
+        let mut builder = builder(1);
+        builder.add_surface(surface);
+        match builder.build() {
+            Err(BuildError::IndexOutOfRange { surface: 0, triangle: 1, index: 3 }) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn vertex_count_mismatch() {
+        let mut short = surface(1);
+        short.add_frame(&POSITIONS[..2], &NORMALS);
+        let mut builder = builder(2);
+        builder.add_surface(short);
+        match builder.build() {
+            Err(BuildError::VertexCountMismatch { surface, frame, expected, found }) => {
+                assert_eq!((surface, frame, expected, found), (0, 1, 3, 2))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn normal_count_mismatch() {
+        let mut short = surface(0);
+        short.add_frame(&POSITIONS, &NORMALS[..1]);
+        let mut builder = builder(1);
+        builder.add_surface(short);
+        match builder.build() {
+            Err(BuildError::NormalCountMismatch { surface, frame, expected, found }) => {
+                assert_eq!((surface, frame, expected, found), (0, 0, 3, 1))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn position_out_of_range() {
+        let mut far = surface(0);
+        far.add_frame(&[POSITIONS[0], POSITIONS[1], Vec3::new(0.0, 600.0, 0.0)], &NORMALS);
+        let mut builder = builder(1);
+        builder.add_surface(far);
+        match builder.build() {
+            Err(BuildError::PositionOutOfRange { surface: 0, frame: 0, vertex: 2 }) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use options::Limit;
+
+pub type Result<T> = result::Result<T, Md3Error>;
+
+/// The structure that was being parsed when an error occurred.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Section {
+    Header,
+    Frame(usize),
+    Tag(usize),
+    Surface(usize),
+    Shader { surface: usize, index: usize },
+    Triangle { surface: usize, index: usize },
+    TexCoord { surface: usize, index: usize },
+    Vertex { surface: usize, frame: usize, index: usize },
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Section::Header => write!(f, "header"),
+            Section::Frame(i) => write!(f, "frame {}", i),
+            Section::Tag(i) => write!(f, "tag {}", i),
+            Section::Surface(i) => write!(f, "surface {}", i),
+            Section::Shader { surface, index } => write!(f, "surface {} shader {}", surface, index),
+            Section::Triangle { surface, index } => {
+                write!(f, "surface {} triangle {}", surface, index)
+            }
+            Section::TexCoord { surface, index } => {
+                write!(f, "surface {} texcoord {}", surface, index)
+            }
+            Section::Vertex { surface, frame, index } => {
+                write!(f, "surface {} frame {} vertex {}", surface, frame, index)
+            }
+        }
+    }
+}
+
+/// Errors returned while loading an MD3 model.
+///
+/// Every variant except `Io` carries the byte offset of the structure that
+/// failed and the section it belongs to.
+#[derive(Debug)]
+pub enum Md3Error {
+    Io(io::Error),
+    BadMagic { offset: u64, section: Section, found: i32 },
+    UnsupportedVersion { offset: u64, section: Section, version: i32 },
+    Truncated { offset: u64, section: Section },
+    CountOutOfRange { offset: u64, section: Section, count: i32 },
+    OffsetOutOfBounds { offset: u64, section: Section, target: i64, len: u64 },
+    RegionOutOfBounds {
+        offset: u64,
+        section: Section,
+        start: i64,
+        end: u64,
+        lower: u64,
+        upper: u64,
+    },
+    Overlap { offset: u64, section: Section, other: Section },
+    /// A structure is stored before `after`, which the standard layout puts
+    /// ahead of it.
+    OutOfOrder { offset: u64, section: Section, after: Section },
+    FrameCountMismatch { offset: u64, section: Section, expected: i32, found: i32 },
+    LimitExceeded {
+        offset: u64,
+        section: Section,
+        limit: Limit,
+        value: u64,
+        max: u64,
+    },
+    /// The model's data cannot be written as a consistent file.
+    Inconsistent { section: Section, reason: &'static str },
+}
+
+impl Md3Error {
+    pub fn offset(&self) -> Option<u64> {
+        match *self {
+            Md3Error::Io(_) |
+            Md3Error::Inconsistent { .. } => None,
+            Md3Error::BadMagic { offset, .. } |
+            Md3Error::UnsupportedVersion { offset, .. } |
+            Md3Error::Truncated { offset, .. } |
+            Md3Error::CountOutOfRange { offset, .. } |
+            Md3Error::OffsetOutOfBounds { offset, .. } |
+            Md3Error::RegionOutOfBounds { offset, .. } |
+            Md3Error::Overlap { offset, .. } |
+            Md3Error::OutOfOrder { offset, .. } |
+            Md3Error::FrameCountMismatch { offset, .. } |
+            Md3Error::LimitExceeded { offset, .. } => Some(offset),
+        }
+    }
+
+    pub fn section(&self) -> Option<Section> {
+        match *self {
+            Md3Error::Io(_) => None,
+            Md3Error::BadMagic { section, .. } |
+            Md3Error::UnsupportedVersion { section, .. } |
+            Md3Error::Truncated { section, .. } |
+            Md3Error::CountOutOfRange { section, .. } |
+            Md3Error::OffsetOutOfBounds { section, .. } |
+            Md3Error::RegionOutOfBounds { section, .. } |
+            Md3Error::Overlap { section, .. } |
+            Md3Error::OutOfOrder { section, .. } |
+            Md3Error::FrameCountMismatch { section, .. } |
+            Md3Error::LimitExceeded { section, .. } |
+            Md3Error::Inconsistent { section, .. } => Some(section),
+        }
+    }
+
+    pub(crate) fn from_read(err: io::Error, offset: u64, section: Section) -> Md3Error {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            Md3Error::Truncated { offset, section }
+        } else {
+            Md3Error::Io(err)
+        }
+    }
+}
+
+impl fmt::Display for Md3Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Md3Error::Io(ref err) => write!(f, "i/o error: {}", err),
+            Md3Error::BadMagic { offset, section, found } => {
+                write!(f, "bad magic {:#010x} in {} at offset {}", found, section, offset)
+            }
+            Md3Error::UnsupportedVersion { offset, section, version } => {
+                write!(f, "unsupported version {} in {} at offset {}", version, section, offset)
+            }
+            Md3Error::Truncated { offset, section } => {
+                write!(f, "truncated data in {} at offset {}", section, offset)
+            }
+            Md3Error::CountOutOfRange { offset, section, count } => {
+                write!(f, "count {} out of range in {} at offset {}", count, section, offset)
+            }
+            Md3Error::OffsetOutOfBounds { offset, section, target, len } => {
+                write!(f,
+                       "offset {} outside file of {} bytes in {} at offset {}",
+                       target,
+                       len,
+                       section,
+                       offset)
+            }
+            Md3Error::RegionOutOfBounds { offset, section, start, end, lower, upper } => {
+                write!(f,
+                       "{} spans {}..{} outside {}..{} (structure at offset {})",
+                       section,
+                       start,
+                       end,
+                       lower,
+                       upper,
+                       offset)
+            }
+            Md3Error::Overlap { offset, section, other } => {
+                write!(f, "{} at offset {} overlaps {}", section, offset, other)
+            }
+            Md3Error::OutOfOrder { offset, section, after } => {
+                write!(f, "{} at offset {} is stored before {}", section, offset, after)
+            }
+            Md3Error::FrameCountMismatch { offset, section, expected, found } => {
+                write!(f,
+                       "{} at offset {} has {} frames, expected {}",
+                       section,
+                       offset,
+                       found,
+                       expected)
+            }
+            Md3Error::LimitExceeded { offset, section, limit, value, max } => {
+                write!(f,
+                       "{} {} exceeds limit of {} in {} at offset {}",
+                       value,
+                       limit,
+                       max,
+                       section,
+                       offset)
+            }
+            Md3Error::Inconsistent { section, reason } => write!(f, "{}: {}", section, reason),
+        }
+    }
+}
+
+impl error::Error for Md3Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Md3Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Md3Error {
+    fn from(err: io::Error) -> Md3Error {
+        Md3Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Md3, Md3Error, Section};
+    use test_model::{self, NUM_FRAMES, OFS_EOF, OFS_TAGS};
+
+    #[test]
+    fn empty_input_is_truncated() {
+        match Md3::from_bytes(&[]) {
+            Err(Md3Error::Truncated { offset: 0, section: Section::Header }) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = test_model::bytes();
+        bytes[..4].copy_from_slice(b"IDP2");
+        match Md3::from_bytes(&bytes) {
+            Err(Md3Error::BadMagic { offset: 0, section: Section::Header, found }) => {
+                assert_eq!(found, i32::from_le_bytes(*b"IDP2"));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut bytes = test_model::bytes();
+        test_model::set_i32(&mut bytes, 4, 16);
+        match Md3::from_bytes(&bytes) {
+            Err(Md3Error::UnsupportedVersion { offset: 4, version: 16, .. }) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_count() {
+        let mut bytes = test_model::bytes();
+        test_model::set_i32(&mut bytes, NUM_FRAMES, -1);
+        match Md3::from_bytes(&bytes) {
+            Err(Md3Error::CountOutOfRange { count: -1, section: Section::Header, .. }) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn offset_past_end() {
+        let mut bytes = test_model::bytes();
+        let len = bytes.len() as i32;
+        test_model::set_i32(&mut bytes, OFS_TAGS, len);
+        match Md3::from_bytes(&bytes) {
+            Err(Md3Error::RegionOutOfBounds { section: Section::Tag(0), .. }) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_file() {
+        let bytes = test_model::bytes();
+        let len = bytes.len() as i64;
+        match Md3::from_bytes(&bytes[..bytes.len() - 1]) {
+            Err(Md3Error::OffsetOutOfBounds { offset: 0, target, len: found, .. }) => {
+                assert_eq!(target, len);
+                assert_eq!(found, len as u64 - 1);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(test_model::get_i32(&bytes, OFS_EOF) as i64, len);
+    }
+
+    #[test]
+    fn every_prefix_fails_without_panicking() {
+        let bytes = test_model::bytes();
+        for len in 0..bytes.len() {
+            let err = Md3::from_bytes(&bytes[..len]).unwrap_err();
+            assert!(!err.to_string().is_empty());
+        }
+    }
+
+    #[test]
+    fn display_names_section_and_offset() {
+        let mut bytes = test_model::bytes();
+        test_model::set_i32(&mut bytes, 4, 16);
+        let err = Md3::from_bytes(&bytes).unwrap_err();
+        assert_eq!(err.offset(), Some(4));
+        assert_eq!(err.section(), Some(Section::Header));
+        assert_eq!(err.to_string(), "unsupported version 16 in header at offset 4");
+    }
+}
@@ -0,0 +1,344 @@
+use std::error;
+use std::fmt;
+
+use {FrameBlend, Mat3, Md3, Orientation, QPath, Vec3};
+
+impl Orientation {
+    /// Places a child on a tag of the model at `self`, as
+    /// `CG_PositionRotatedEntityOnTag` does.
+    ///
+    /// `tag` is the tag's blended orientation in the parent's model space and
+    /// `rotation` is the child's own rotation relative to the tag.
+    pub fn attach(&self, tag: &Orientation, rotation: &Mat3) -> Orientation {
+        let mut origin = self.origin;
+        for i in 0..3 {
+            origin += self.axis[i] * tag.origin[i];
+        }
+        Orientation {
+            origin,
+            axis: *rotation * tag.axis * self.axis,
+        }
+    }
+}
+
+/// Identifies a part of a `Hierarchy`.
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Hash)]
+pub struct PartId(usize);
+
+impl PartId {
+    /// Position of the part in the output of `Hierarchy::solve`.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum AttachError {
+    /// The part does not belong to this hierarchy. Since a part can only
+    /// hang from one added before it, this is also what an attempt to build
+    /// a cycle gets.
+    UnknownPart(PartId),
+    /// The parent has no tag of that name in `frame`.
+    MissingTag { part: PartId, frame: usize, tag: String },
+}
+
+impl fmt::Display for AttachError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AttachError::UnknownPart(part) => write!(f, "part {} does not exist", part.0),
+            AttachError::MissingTag { part, frame, ref tag } => {
+                write!(f, "part {} has no tag {} in frame {}", part.0, tag, frame)
+            }
+        }
+    }
+}
+
+impl error::Error for AttachError {}
+
+struct Part<'a> {
+    model: &'a Md3,
+    /// The parent and the name of the parent's tag the part hangs from.
+    parent: Option<(PartId, QPath)>,
+    frames: FrameBlend,
+    rotation: Mat3,
+}
+
+/// Models joined at tags, such as a player's legs, torso, head and weapon.
+///
+/// Parts are added parent first, so each part's transform can be solved
+/// from its parent's in one pass.
+pub struct Hierarchy<'a> {
+    origin: Vec3,
+    parts: Vec<Part<'a>>,
+}
+
+impl<'a> Hierarchy<'a> {
+    pub fn new(root: &'a Md3) -> Hierarchy<'a> {
+        Hierarchy {
+            origin: Vec3::ZERO,
+            parts: vec![Part {
+                            model: root,
+                            parent: None,
+                            frames: FrameBlend::default(),
+                            rotation: Mat3::IDENTITY,
+                        }],
+        }
+    }
+
+    pub fn root(&self) -> PartId {
+        PartId(0)
+    }
+
+    /// Hangs `child` from the tag named `tag` of `parent`.
+    pub fn attach(&mut self,
+                  parent: PartId,
+                  tag: &str,
+                  child: &'a Md3)
+                  -> Result<PartId, AttachError> {
+        let model = self.part(parent)?.model;
+        let name = match model.tag(0, tag) {
+            Some(found) => found.name,
+            None => {
+                return Err(AttachError::MissingTag {
+                    part: parent,
+                    frame: 0,
+                    tag: tag.to_string(),
+                })
+            }
+        };
+        self.parts.push(Part {
+            model: child,
+            parent: Some((parent, name)),
+            frames: FrameBlend::default(),
+            rotation: Mat3::IDENTITY,
+        });
+        Ok(PartId(self.parts.len() - 1))
+    }
+
+    pub fn model(&self, part: PartId) -> Option<&'a Md3> {
+        self.parts.get(part.0).map(|p| p.model)
+    }
+
+    /// Sets the world position of the root part.
+    pub fn set_origin(&mut self, origin: Vec3) {
+        self.origin = origin;
+    }
+
+    /// Sets the frames a part is shown at.
+    pub fn set_frames(&mut self, part: PartId, frames: FrameBlend) -> Result<(), AttachError> {
+        self.part_mut(part)?.frames = frames;
+        Ok(())
+    }
+
+    /// Sets a part's own rotation: the world rotation for the root, and
+    /// the rotation relative to its tag for any other part.
+    pub fn set_rotation(&mut self, part: PartId, rotation: Mat3) -> Result<(), AttachError> {
+        self.part_mut(part)?.rotation = rotation;
+        Ok(())
+    }
+
+    /// World orientation of every part, indexed by `PartId::index`.
+    pub fn solve(&self) -> Result<Vec<Orientation>, AttachError> {
+        let mut out: Vec<Orientation> = Vec::with_capacity(self.parts.len());
+        for part in &self.parts {
+            let orientation = match part.parent {
+                None => {
+                    Orientation {
+                        origin: self.origin,
+                        axis: part.rotation,
+                    }
+                }
+                Some((parent, ref tag)) => {
+                    let frames = self.parts[parent.0].frames;
+                    let model = self.parts[parent.0].model;
+                    let lerped = match model.lerp_tag(frames.old_frame,
+                                                      frames.frame,
+                                                      frames.t(),
+                                                      tag) {
+                        Some(lerped) => lerped,
+                        None => {
+                            let frame = if model.tag(frames.old_frame, tag).is_none() {
+                                frames.old_frame
+                            } else {
+                                frames.frame
+                            };
+                            return Err(AttachError::MissingTag {
+                                part: parent,
+                                frame,
+                                tag: tag.to_string_lossy().into_owned(),
+                            });
+                        }
+                    };
+                    out[parent.0].attach(&lerped, &part.rotation)
+                }
+            };
+            out.push(orientation);
+        }
+        Ok(out)
+    }
+
+    fn part(&self, part: PartId) -> Result<&Part<'a>, AttachError> {
+        self.parts.get(part.0).ok_or(AttachError::UnknownPart(part))
+    }
+
+    fn part_mut(&mut self, part: PartId) -> Result<&mut Part<'a>, AttachError> {
+        self.parts.get_mut(part.0).ok_or(AttachError::UnknownPart(part))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {FrameBlend, Mat3, Md3, Md3Builder, Orientation, Vec3};
+    use super::{AttachError, Hierarchy, PartId};
+
+    /// A quarter turn about z: forward becomes left.
+    const TURNED: Mat3 = Mat3 {
+        rows: [Vec3 {
+                   x: 0.0,
+                   y: 1.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: -1.0,
+                   y: 0.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: 0.0,
+                   y: 0.0,
+                   z: 1.0,
+               }],
+    };
+
+    /// A half turn about z.
+    const REVERSED: Mat3 = Mat3 {
+        rows: [Vec3 {
+                   x: -1.0,
+                   y: 0.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: 0.0,
+                   y: -1.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: 0.0,
+                   y: 0.0,
+                   z: 1.0,
+               }],
+    };
+
+    /// A model with one frame per entry of `tags`, each holding the tag
+    /// `name` at that orientation.
+    fn model(name: &str, tags: &[(Vec3, Mat3)]) -> Md3 {
+        let mut builder = Md3Builder::new("models/part.md3").unwrap();
+        for (i, &(origin, axis)) in tags.iter().enumerate() {
+            let frame = builder.add_frame(&format!("frame{}", i)).unwrap();
+            builder.add_tag(frame, name, origin, axis).unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    fn assert_orientation(a: &Orientation, origin: Vec3, axis: Mat3) {
+        assert!((a.origin - origin).length() < 1e-5, "{:?} != {:?}", a.origin, origin);
+        for i in 0..3 {
+            assert!((a.axis[i] - axis[i]).length() < 1e-5, "{:?} != {:?}", a.axis, axis);
+        }
+    }
+
+    #[test]
+    fn identity_attach() {
+        let tag = Orientation {
+            origin: Vec3::new(1.0, 2.0, 3.0),
+            axis: TURNED,
+        };
+        let placed = Orientation::default().attach(&tag, &Mat3::IDENTITY);
+        assert_orientation(&placed, tag.origin, TURNED);
+    }
+
+    #[test]
+    fn attach_to_rotated_parent() {
+        // Worked through CG_PositionRotatedEntityOnTag by hand: the tag's
+        // forward offset follows the parent's forward axis, which points
+        // along y, and the child's own turn comes first.
+        let parent = Orientation {
+            origin: Vec3::new(100.0, 0.0, 0.0),
+            axis: TURNED,
+        };
+        let tag = Orientation {
+            origin: Vec3::new(10.0, 0.0, 5.0),
+            axis: Mat3::IDENTITY,
+        };
+        assert_orientation(&parent.attach(&tag, &Mat3::IDENTITY),
+                           Vec3::new(100.0, 10.0, 5.0),
+                           TURNED);
+        assert_orientation(&parent.attach(&tag, &TURNED), Vec3::new(100.0, 10.0, 5.0), REVERSED);
+    }
+
+    #[test]
+    fn solves_a_chain() {
+        let legs = model("tag_torso",
+                         &[(Vec3::new(0.0, 0.0, 20.0), Mat3::IDENTITY),
+                           (Vec3::new(0.0, 0.0, 24.0), Mat3::IDENTITY)]);
+        let torso = model("tag_head", &[(Vec3::new(3.0, 0.0, 10.0), TURNED)]);
+        let head = model("tag_none", &[(Vec3::ZERO, Mat3::IDENTITY)]);
+
+        let mut hierarchy = Hierarchy::new(&legs);
+        let root = hierarchy.root();
+        let torso_id = hierarchy.attach(root, "tag_torso", &torso).unwrap();
+        let head_id = hierarchy.attach(torso_id, "tag_head", &head).unwrap();
+        hierarchy.set_origin(Vec3::new(5.0, 0.0, 0.0));
+        let halfway = FrameBlend {
+            old_frame: 0,
+            frame: 1,
+            backlerp: 0.5,
+        };
+        hierarchy.set_frames(root, halfway).unwrap();
+        hierarchy.set_rotation(torso_id, TURNED).unwrap();
+
+        let solved = hierarchy.solve().unwrap();
+        assert_eq!(solved.len(), 3);
+        assert_orientation(&solved[root.index()], Vec3::new(5.0, 0.0, 0.0), Mat3::IDENTITY);
+        assert_orientation(&solved[torso_id.index()], Vec3::new(5.0, 0.0, 22.0), TURNED);
+        // The head tag sits 3 units along the torso's forward axis, which
+        // now points along y, and turns the head a further quarter turn.
+        assert_orientation(&solved[head_id.index()], Vec3::new(5.0, 3.0, 32.0), REVERSED);
+    }
+
+    #[test]
+    fn unknown_parts() {
+        let legs = model("tag_torso", &[(Vec3::ZERO, Mat3::IDENTITY)]);
+        let mut hierarchy = Hierarchy::new(&legs);
+        // The part this would add gets id 1, so it cannot hang from itself.
+        assert_eq!(hierarchy.attach(PartId(1), "tag_torso", &legs),
+                   Err(AttachError::UnknownPart(PartId(1))));
+        assert_eq!(hierarchy.set_frames(PartId(1), FrameBlend::single(0)),
+                   Err(AttachError::UnknownPart(PartId(1))));
+        assert_eq!(hierarchy.set_rotation(PartId(1), Mat3::IDENTITY),
+                   Err(AttachError::UnknownPart(PartId(1))));
+        assert!(hierarchy.model(PartId(1)).is_none());
+    }
+
+    #[test]
+    fn missing_tags() {
+        let legs = model("tag_torso", &[(Vec3::ZERO, Mat3::IDENTITY)]);
+        let mut hierarchy = Hierarchy::new(&legs);
+        let root = hierarchy.root();
+        assert_eq!(hierarchy.attach(root, "tag_head", &legs),
+                   Err(AttachError::MissingTag {
+                       part: root,
+                       frame: 0,
+                       tag: "tag_head".to_string(),
+                   }));
+
+        hierarchy.attach(root, "tag_torso", &legs).unwrap();
+        hierarchy.set_frames(root, FrameBlend::single(3)).unwrap();
+        assert_eq!(hierarchy.solve(),
+                   Err(AttachError::MissingTag {
+                       part: root,
+                       frame: 3,
+                       tag: "tag_torso".to_string(),
+                   }));
+    }
+}
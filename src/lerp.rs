@@ -0,0 +1,174 @@
+use {Md3, Surface, Vec3};
+use vertex::{decode_normal, MD3_XYZ_SCALE};
+
+/// Two frames and the blend between them, as in a `refEntity_t`.
+///
+/// `backlerp` is the weight of `old_frame`: 0 shows `frame` alone and 1
+/// shows `old_frame` alone.
+#[derive(Debug,Copy,Clone,PartialEq,Default)]
+pub struct FrameBlend {
+    pub old_frame: usize,
+    pub frame: usize,
+    pub backlerp: f32,
+}
+
+impl FrameBlend {
+    /// Shows `frame` alone.
+    pub fn single(frame: usize) -> FrameBlend {
+        FrameBlend {
+            old_frame: frame,
+            frame,
+            backlerp: 0.0,
+        }
+    }
+
+    /// The weight of `frame`, as passed as `t` to the lerp functions.
+    pub fn t(&self) -> f32 {
+        1.0 - self.backlerp
+    }
+}
+
+/// Bounds of a frame blended between two frames.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct FrameBounds {
+    pub min_bounds: Vec3,
+    pub max_bounds: Vec3,
+    pub local_origin: Vec3,
+    pub radius: f32,
+}
+
+impl Surface {
+    /// Blends `frame_a` (at `t = 0`) with `frame_b` (at `t = 1`) into the
+    /// caller's buffers, the way the renderer's `LerpMeshVertexes` does with
+    /// `backlerp = 1 - t`. Normals are renormalized after blending.
+    ///
+    /// Nothing is allocated. Returns `false` without writing anything if
+    /// either frame does not exist or a buffer is shorter than the vertex
+    /// count; extra buffer entries are left untouched.
+    #[must_use]
+    pub fn lerp(&self,
+                frame_a: usize,
+                frame_b: usize,
+                t: f32,
+                positions: &mut [Vec3],
+                normals: &mut [Vec3])
+                -> bool {
+        let (a, b) = match (self.vertices.get(frame_a), self.vertices.get(frame_b)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return false,
+        };
+        let count = a.len().min(b.len());
+        if positions.len() < count || normals.len() < count {
+            return false;
+        }
+
+        let scale_a = MD3_XYZ_SCALE * (1.0 - t);
+        let scale_b = MD3_XYZ_SCALE * t;
+        for (i, (va, vb)) in a.iter().zip(b).enumerate() {
+            positions[i] = Vec3::new(va.x as f32 * scale_a + vb.x as f32 * scale_b,
+                                     va.y as f32 * scale_a + vb.y as f32 * scale_b,
+                                     va.z as f32 * scale_a + vb.z as f32 * scale_b);
+            normals[i] = decode_normal(va.normal).lerp(decode_normal(vb.normal), t).normalize();
+        }
+        true
+    }
+}
+
+impl Md3 {
+    /// Blends the stored bounds of `frame_a` (at `t = 0`) and `frame_b`
+    /// (at `t = 1`), or returns `None` if either frame does not exist.
+    pub fn lerp_frame_bounds(&self, frame_a: usize, frame_b: usize, t: f32) -> Option<FrameBounds> {
+        let a = self.frames.get(frame_a)?;
+        let b = self.frames.get(frame_b)?;
+        Some(FrameBounds {
+            min_bounds: a.min_bounds.lerp(b.min_bounds, t),
+            max_bounds: a.max_bounds.lerp(b.max_bounds, t),
+            local_origin: a.local_origin.lerp(b.local_origin, t),
+            radius: a.radius + (b.radius - a.radius) * t,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Vec3;
+    use vertex::encode_normal;
+    use test_model;
+
+    fn lerped(t: f32) -> (Vec<Vec3>, Vec<Vec3>) {
+        let md3 = test_model::model();
+        let mut positions = vec![Vec3::ZERO; 3];
+        let mut normals = vec![Vec3::ZERO; 3];
+        assert!(md3.surfaces[0].lerp(0, 1, t, &mut positions, &mut normals));
+        (positions, normals)
+    }
+
+    #[test]
+    fn endpoints_match_the_frames() {
+        let md3 = test_model::model();
+        let surface = &md3.surfaces[0];
+        for &(t, frame) in &[(0.0, 0), (1.0, 1)] {
+            let (positions, normals) = lerped(t);
+            assert_eq!(positions, surface.positions(frame).collect::<Vec<_>>());
+            // The table's normals are a little short of unit length.
+            let expected: Vec<_> = surface.normals(frame).map(Vec3::normalize).collect();
+            assert_eq!(normals, expected);
+        }
+    }
+
+    #[test]
+    fn midpoint_positions() {
+        let (positions, _) = lerped(0.5);
+        assert_eq!(positions,
+                   [Vec3::ZERO, Vec3::new(12.0, 0.0, 0.0), Vec3::new(0.0, 12.0, 6.0)]);
+    }
+
+    #[test]
+    fn normals_are_renormalized() {
+        let mut md3 = test_model::model();
+        md3.surfaces[0].vertices[1][0].normal = encode_normal(Vec3::new(1.0, 0.0, 0.0));
+        let mut positions = [Vec3::ZERO; 3];
+        let mut normals = [Vec3::ZERO; 3];
+        assert!(md3.surfaces[0].lerp(0, 1, 0.5, &mut positions, &mut normals));
+        assert!((normals[0].length() - 1.0).abs() < 1e-5);
+        assert!((normals[0].x - normals[0].z).abs() < 0.01, "{:?}", normals[0]);
+    }
+
+    #[test]
+    fn rejects_bad_frames_and_short_buffers() {
+        let md3 = test_model::model();
+        let surface = &md3.surfaces[0];
+        let mut positions = [Vec3::new(1.0, 1.0, 1.0); 3];
+        let mut normals = [Vec3::new(1.0, 1.0, 1.0); 3];
+        assert!(!surface.lerp(0, 2, 0.5, &mut positions, &mut normals));
+        assert!(!surface.lerp(2, 0, 0.5, &mut positions, &mut normals));
+        assert!(!surface.lerp(0, 1, 0.5, &mut positions[..2], &mut normals));
+        assert!(!surface.lerp(0, 1, 0.5, &mut positions, &mut normals[..2]));
+        assert_eq!(positions, [Vec3::new(1.0, 1.0, 1.0); 3]);
+        assert_eq!(normals, [Vec3::new(1.0, 1.0, 1.0); 3]);
+
+        // Longer buffers are fine; the extra entries are left alone.
+        let mut positions = [Vec3::new(1.0, 1.0, 1.0); 4];
+        let mut normals = [Vec3::ZERO; 4];
+        assert!(surface.lerp(0, 1, 0.0, &mut positions, &mut normals));
+        assert_eq!(positions[3], Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn frame_bounds() {
+        let md3 = test_model::model();
+        for &(t, frame) in &[(0.0, 0), (1.0, 1)] {
+            let bounds = md3.lerp_frame_bounds(0, 1, t).unwrap();
+            assert_eq!(bounds.min_bounds, md3.frames[frame].min_bounds);
+            assert_eq!(bounds.max_bounds, md3.frames[frame].max_bounds);
+            assert_eq!(bounds.radius, md3.frames[frame].radius);
+        }
+        let mid = md3.lerp_frame_bounds(0, 1, 0.5).unwrap();
+        assert_eq!(mid.min_bounds, Vec3::ZERO);
+        assert_eq!(mid.max_bounds, Vec3::new(12.0, 12.0, 6.0));
+        assert_eq!(mid.local_origin, Vec3::ZERO);
+        assert!((mid.radius - 18.0).abs() < 1e-5);
+        assert_eq!(md3.lerp_frame_bounds(0, 2, 0.5), None);
+        assert_eq!(md3.lerp_frame_bounds(2, 0, 0.5), None);
+    }
+}
@@ -1,34 +1,117 @@
 
 extern crate byteorder;
+extern crate bytemuck;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+
+mod animation;
+mod bounds;
+mod buffer;
+mod builder;
+mod error;
+mod hierarchy;
+mod lerp;
+mod math;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod name;
+mod options;
+mod player;
+mod probe;
+mod shader_script;
+mod skin;
+mod source;
+mod tag;
+#[cfg(test)]
+mod test_model;
+mod token;
+mod validate;
+mod vertex;
+mod view;
+mod warning;
+mod write;
 
 use byteorder::{ReadBytesExt, LittleEndian};
-use std::io::{Cursor,Read,Error,ErrorKind,Result};
+use std::io::{BufReader,Cursor,Read,Seek};
 use std::path::Path;
 use std::fs::File;
 
+use source::Source;
+use validate::Layout;
+
+pub use animation::{AnimationConfig, Animation, AnimationId, AnimationError, AnimationPlayer,
+                    AnimationErrorKind, Footsteps, Sex, MAX_ANIMATIONS, MAX_TOTALANIMATIONS};
+pub use bounds::{BoundsSource, BoundsMismatch};
+pub use buffer::{VertexLayout, VertexBuffers, Arrangement, Attribute, PositionFormat, NormalFormat,
+                 TexCoordFormat, SNORM16_POSITION_SCALE};
+pub use builder::{Md3Builder, SurfaceBuilder, BuildError};
+pub use error::{Md3Error, Result, Section};
+pub use hierarchy::{Hierarchy, PartId, AttachError};
+pub use lerp::{FrameBlend, FrameBounds};
+pub use math::{Mat3, Quat};
+#[cfg(feature = "mmap")]
+pub use mmap::MappedMd3;
+pub use name::{FixedName, QPath, FrameName, NameError};
+pub use options::{LoadOptions, Limit, MD3_MAX_FRAMES, MD3_MAX_TAGS, MD3_MAX_SURFACES,
+                  MD3_MAX_SHADERS, MD3_MAX_VERTS, MD3_MAX_TRIANGLES};
+pub use player::{PlayerModel, PlayerPart, PlayerRig, PlayerError};
+pub use probe::{Md3Probe, SurfaceProbe};
+pub use shader_script::{ShaderScript, ShaderDef, ShaderLibrary, ShaderError, ShaderErrorKind, Span,
+                        Global, GlobalKind, Stage, StageDirective, StageKind, Cull, Deform,
+                        MapSource, BlendFunc, BlendFactor, RgbGen, TcMod, AlphaFunc, Wave,
+                        WaveFunc};
+pub use skin::{Skin, SkinEntry, SkinResolution};
+pub use tag::{Orientation, TagSelector};
+pub use validate::Report;
+pub use warning::{Warning, WarningKind};
+pub use vertex::{MD3_XYZ_SCALE, Quantized, quantize_position, decode_normal,
+                 decode_normal_analytic, encode_normal};
+pub use view::{Md3View, FrameView, TagView, SurfaceView, ShaderView};
+
 const MD3_MAGIC: i32 = 0x33504449;
+const MD3_VERSION: i32 = 15;
 const MAX_QPATH: usize = 64;
 
-#[derive(Debug,Copy,Clone)]
+const HEADER_SIZE: u64 = 108;
+const FRAME_SIZE: u64 = 56;
+const TAG_SIZE: u64 = 112;
+const SURFACE_HEADER_SIZE: u64 = 108;
+const SHADER_SIZE: u64 = 68;
+const TRIANGLE_SIZE: u64 = 12;
+const ST_SIZE: u64 = 8;
+const XYZNORMAL_SIZE: u64 = 8;
+
+#[derive(Debug,Copy,Clone,PartialEq,Default)]
+#[repr(C)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
     pub z: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Md3 {
     pub header: Md3Header,
     pub frames: Vec<Frame>,
+    /// `num_frames * num_tags` tags, grouped by frame.
     pub tags: Vec<Tag>,
     pub surfaces: Vec<Surface>,
+    /// Bytes of the source file not covered by any structure. They are
+    /// written back as long as the original layout can be kept.
+    pub padding: Vec<Padding>,
+}
+
+#[derive(Debug,Clone)]
+pub struct Padding {
+    pub offset: u64,
+    pub bytes: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone,PartialEq)]
 pub struct Md3Header {
     pub ident: i32,
     pub version: i32,
-    pub name: String,
+    pub name: QPath,
     pub flags: i32,
     pub num_frames: i32,
     pub num_tags: i32,
@@ -40,26 +123,26 @@ pub struct Md3Header {
     pub ofs_eof: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Frame {
     pub min_bounds: Vec3,
     pub max_bounds: Vec3,
     pub local_origin: Vec3,
     pub radius: f32,
-    pub name: String,
+    pub name: FrameName,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Tag {
-    pub name: String,
+    pub name: QPath,
     pub origin: Vec3,
-    pub axis: [Vec3; 3],
+    pub axis: Mat3,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone,PartialEq)]
 pub struct SurfaceHeader {
     pub ident: i32,
-    pub name: String,
+    pub name: QPath,
     pub flags: i32,
     pub num_frames: i32,
     pub num_shaders: i32,
@@ -72,13 +155,13 @@ pub struct SurfaceHeader {
     pub ofs_end: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Shader {
-    pub name: String,
+    pub name: QPath,
     pub shader_index: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Surface {
     pub header: SurfaceHeader,
     pub shaders: Vec<Shader>,
@@ -87,17 +170,17 @@ pub struct Surface {
     pub vertices: Vec<Vec<Vertex>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Copy,Clone)]
 pub struct Triangle {
     pub indexes: [i32; 3],
 }
 
-#[derive(Debug)]
+#[derive(Debug,Copy,Clone)]
 pub struct TexCoord {
     pub st: [f32; 2],
 }
 
-#[derive(Debug)]
+#[derive(Debug,Copy,Clone)]
 pub struct Vertex {
     pub x: i16,
     pub y: i16,
@@ -108,189 +191,417 @@ pub struct Vertex {
 impl Md3 {
 
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Md3> {
-        let mut bytes = Vec::new();
-        let mut file = File::open(path)?;
-        file.read_to_end(&mut bytes)?;
-        Md3::from_bytes(&bytes)
+        let file = File::open(path)?;
+        Md3::from_reader(BufReader::new(file))
+    }
+
+    /// Checks every offset and count in `bytes` without decoding the model.
+    ///
+    /// Overlapping structures and structures stored out of the standard
+    /// order are both reported; `from_bytes_lenient` loads the latter with a
+    /// warning.
+    pub fn validate(bytes: &[u8]) -> Report {
+        match Source::new(Cursor::new(bytes)) {
+            Ok(mut buff) => validate::validate(&mut buff),
+            Err(err) => Report { issues: vec![err.into()] },
+        }
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Md3> {
-        let mut buff = Cursor::new(bytes);
+        Md3::from_bytes_with(bytes, &LoadOptions::default())
+    }
 
-        if Md3::read_s32(&mut buff) == MD3_MAGIC {
+    /// Loads a model, rejecting it before any allocation if it exceeds the
+    /// caps in `options`.
+    pub fn from_bytes_with(bytes: &[u8], options: &LoadOptions) -> Result<Md3> {
+        Md3::from_reader_with(Cursor::new(bytes), options)
+    }
 
-            buff.set_position(0);
-            let md3_header = Md3::read_md3_header(&mut buff);
+    /// Loads a model from any seekable source, starting at the reader's
+    /// current position. Unbuffered readers should be wrapped in a
+    /// `BufReader`.
+    pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Md3> {
+        Md3::from_reader_with(reader, &LoadOptions::default())
+    }
 
-            buff.set_position(md3_header.ofs_frames as u64);
-            let frames = Md3::read_many(&mut buff, Md3::read_frame, md3_header.num_frames as usize);
+    pub fn from_reader_with<R: Read + Seek>(reader: R, options: &LoadOptions) -> Result<Md3> {
+        let mut buff = Source::new(reader)?;
+        let layout = validate::check(&mut buff)?;
+        options.check(&layout)?;
+        Md3::read_layout(&mut buff, layout)
+    }
 
-            buff.set_position(md3_header.ofs_tags as u64);
-            let tags = Md3::read_many(&mut buff, Md3::read_tag, md3_header.num_tags as usize);
+    /// Loads a model, tolerating or repairing common defects instead of
+    /// failing. Every assumption made is reported as a `Warning`.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> Result<(Md3, Vec<Warning>)> {
+        Md3::from_reader_lenient(Cursor::new(bytes), &LoadOptions::default())
+    }
 
-            buff.set_position(md3_header.ofs_surfaces as u64);
-            let surfaces = Md3::read_many(&mut buff,
-                                          Md3::read_surface,
-                                          md3_header.num_surfaces as usize);
+    pub fn from_reader_lenient<R: Read + Seek>(reader: R,
+                                               options: &LoadOptions)
+                                               -> Result<(Md3, Vec<Warning>)> {
+        let mut buff = Source::lenient(reader)?;
+        let layout = validate::check(&mut buff)?;
+        options.check(&layout)?;
+        let md3 = Md3::read_layout(&mut buff, layout)?;
+        md3.check_bounds(&mut buff);
+        Ok((md3, buff.take_warnings()))
+    }
 
-            let md3 = Md3 {
-                header: md3_header,
-                frames: frames,
-                tags: tags,
-                surfaces: surfaces,
-            };
+    /// Reads the header, surface headers, shader names and tag names,
+    /// skipping all per-vertex and per-frame payloads.
+    pub fn probe(bytes: &[u8]) -> Result<Md3Probe> {
+        Md3::probe_reader(Cursor::new(bytes))
+    }
 
-            Ok(md3)
-        } else {
-            Err(Error::new(ErrorKind::InvalidData, "MD3 magic not matching"))
+    pub fn probe_file<P: AsRef<Path>>(path: P) -> Result<Md3Probe> {
+        let file = File::open(path)?;
+        Md3::probe_reader(BufReader::new(file))
+    }
+
+    pub fn probe_reader<R: Read + Seek>(reader: R) -> Result<Md3Probe> {
+        let mut buff = Source::new(reader)?;
+        probe::probe(&mut buff)
+    }
+
+    /// Decodes a model whose layout has already been validated.
+    fn read_layout<R: Read + Seek>(buff: &mut Source<R>, layout: Layout) -> Result<Md3> {
+        let md3_header = layout.header;
+
+        let num_frames = Md3::count(md3_header.num_frames, 0, Section::Header)?;
+        let num_tags = Md3::count(md3_header.num_tags, 0, Section::Header)?;
+
+        Md3::seek(buff, 0, md3_header.ofs_frames, Section::Header)?;
+        let frames = Md3::read_many(buff, num_frames, Md3::read_frame)?;
+
+        Md3::seek(buff, 0, md3_header.ofs_tags, Section::Header)?;
+        let tags = Md3::read_many(buff, num_frames * num_tags, Md3::read_tag)?;
+
+        let mut surfaces = Vec::with_capacity(layout.surfaces.len());
+        for (index, surface) in layout.surfaces.into_iter().enumerate() {
+            surfaces.push(Md3::read_surface(buff,
+                                            index,
+                                            surface.offset,
+                                            surface.header,
+                                            num_frames)?);
         }
+
+        let mut padding = Vec::with_capacity(layout.gaps.len());
+        for (start, end) in layout.gaps {
+            buff.set_position(start)?;
+            let mut bytes = vec![0; (end - start) as usize];
+            buff.read_exact(&mut bytes)
+                .map_err(|e| Md3Error::from_read(e, start, Section::Header))?;
+            padding.push(Padding {
+                offset: start,
+                bytes,
+            });
+        }
+
+        let md3 = Md3 {
+            header: md3_header,
+            frames,
+            tags,
+            surfaces,
+            padding,
+        };
+
+        Ok(md3)
     }
 
-    fn read_many<T, F>(buff: &mut Cursor<&[u8]>, reader: F, count: usize) -> Vec<T>
-        where F: Fn(&mut Cursor<&[u8]>) -> T
+    fn read_many<R, T, F>(buff: &mut Source<R>, count: usize, mut reader: F) -> Result<Vec<T>>
+        where R: Read + Seek,
+              F: FnMut(&mut Source<R>, usize) -> Result<T>
     {
         let mut vec = Vec::new();
-        for _ in 0..count {
-            vec.push(reader(buff))
+        for i in 0..count {
+            vec.push(reader(buff, i)?)
         }
-        vec
+        Ok(vec)
     }
 
-    fn read_s32(buff: &mut Cursor<&[u8]>) -> i32 {
-        buff.read_i32::<LittleEndian>().unwrap()
+    fn count(value: i32, offset: u64, section: Section) -> Result<usize> {
+        if value < 0 {
+            Err(Md3Error::CountOutOfRange {
+                offset,
+                section,
+                count: value,
+            })
+        } else {
+            Ok(value as usize)
+        }
     }
 
-    fn read_s16(buff: &mut Cursor<&[u8]>) -> i16 {
-        buff.read_i16::<LittleEndian>().unwrap()
+    fn seek<R: Read + Seek>(buff: &mut Source<R>,
+                            base: u64,
+                            ofs: i32,
+                            section: Section)
+                            -> Result<()> {
+        let len = buff.size();
+        let target = base as i64 + ofs as i64;
+        if target < 0 || target as u64 > len {
+            return Err(Md3Error::OffsetOutOfBounds {
+                offset: base,
+                section,
+                target,
+                len,
+            });
+        }
+        buff.set_position(target as u64)?;
+        Ok(())
     }
 
-    fn read_f32(buff: &mut Cursor<&[u8]>) -> f32 {
-        buff.read_f32::<LittleEndian>().unwrap()
+    fn read_s32<R: Read + Seek>(buff: &mut Source<R>, section: Section) -> Result<i32> {
+        let offset = buff.position();
+        buff.read_i32::<LittleEndian>().map_err(|e| Md3Error::from_read(e, offset, section))
     }
 
-    fn read_vec3(buff: &mut Cursor<&[u8]>) -> Vec3 {
-        Vec3 {
-            x: Md3::read_f32(buff),
-            y: Md3::read_f32(buff),
-            z: Md3::read_f32(buff),
-        }
+    fn read_s16<R: Read + Seek>(buff: &mut Source<R>, section: Section) -> Result<i16> {
+        let offset = buff.position();
+        buff.read_i16::<LittleEndian>().map_err(|e| Md3Error::from_read(e, offset, section))
     }
 
-    fn read_string(buff: &mut Cursor<&[u8]>, len: usize) -> String {
-        let mut bytes = Vec::new();
-        for _ in 0..len {
-            bytes.push(buff.read_u8().unwrap())
-        }
-        String::from_utf8(bytes.into_iter().take_while(|x| *x != '\0' as u8).collect()).unwrap()
-    }
-
-    fn read_md3_header(buff: &mut Cursor<&[u8]>) -> Md3Header {
-        Md3Header {
-            ident: Md3::read_s32(buff),
-            version: Md3::read_s32(buff),
-            name: Md3::read_string(buff, MAX_QPATH),
-            flags: Md3::read_s32(buff),
-            num_frames: Md3::read_s32(buff),
-            num_tags: Md3::read_s32(buff),
-            num_surfaces: Md3::read_s32(buff),
-            num_skins: Md3::read_s32(buff),
-            ofs_frames: Md3::read_s32(buff),
-            ofs_tags: Md3::read_s32(buff),
-            ofs_surfaces: Md3::read_s32(buff),
-            ofs_eof: Md3::read_s32(buff),
-        }
+    fn read_f32<R: Read + Seek>(buff: &mut Source<R>, section: Section) -> Result<f32> {
+        let offset = buff.position();
+        buff.read_f32::<LittleEndian>().map_err(|e| Md3Error::from_read(e, offset, section))
     }
 
-    fn read_frame(buff: &mut Cursor<&[u8]>) -> Frame {
-        Frame {
-            min_bounds: Md3::read_vec3(buff),
-            max_bounds: Md3::read_vec3(buff),
-            local_origin: Md3::read_vec3(buff),
-            radius: Md3::read_f32(buff),
-            name: Md3::read_string(buff, 16),
-        }
+    fn read_vec3<R: Read + Seek>(buff: &mut Source<R>, section: Section) -> Result<Vec3> {
+        Ok(Vec3 {
+            x: Md3::read_f32(buff, section)?,
+            y: Md3::read_f32(buff, section)?,
+            z: Md3::read_f32(buff, section)?,
+        })
     }
 
-    fn read_tag(buff: &mut Cursor<&[u8]>) -> Tag {
-        Tag {
-            name: Md3::read_string(buff, MAX_QPATH),
-            origin: Md3::read_vec3(buff),
-            axis: [Md3::read_vec3(buff), Md3::read_vec3(buff), Md3::read_vec3(buff)],
+    fn read_name<R: Read + Seek, const N: usize>(buff: &mut Source<R>,
+                                                 section: Section)
+                                                 -> Result<FixedName<N>> {
+        let offset = buff.position();
+        let mut raw = [0; N];
+        buff.read_exact(&mut raw).map_err(|e| Md3Error::from_read(e, offset, section))?;
+        let name = FixedName::from_raw(raw);
+        // The engine copies names with `Q_strncpyz`, so a name filling its
+        // whole field loads; only a lenient load reports it.
+        if !name.is_terminated() {
+            buff.warn(Warning {
+                kind: WarningKind::UnterminatedName,
+                offset,
+                section,
+                assumed: format!("used all {} bytes as the name", N),
+            });
+        } else if name.to_str().is_err() {
+            buff.warn(Warning {
+                kind: WarningKind::InvalidName,
+                offset,
+                section,
+                assumed: "kept the raw bytes".to_string(),
+            });
         }
+        Ok(name)
     }
 
-    fn read_surface(buff: &mut Cursor<&[u8]>) -> Surface {
-        let surface_start = buff.position();
-        let surface_header = Md3::read_surface_header(buff);
+    fn read_md3_header<R: Read + Seek>(buff: &mut Source<R>) -> Result<Md3Header> {
+        let section = Section::Header;
+
+        let ident = Md3::read_s32(buff, section)?;
+        if ident != MD3_MAGIC {
+            return Err(Md3Error::BadMagic {
+                offset: 0,
+                section,
+                found: ident,
+            });
+        }
+
+        let version = Md3::read_s32(buff, section)?;
+        if version != MD3_VERSION {
+            return Err(Md3Error::UnsupportedVersion {
+                offset: 4,
+                section,
+                version,
+            });
+        }
 
-        buff.set_position(surface_start + surface_header.ofs_shaders as u64);
-        let shaders = Md3::read_many(buff, Md3::read_shader, surface_header.num_shaders as usize);
+        Ok(Md3Header {
+            ident,
+            version,
+            name: Md3::read_name(buff, section)?,
+            flags: Md3::read_s32(buff, section)?,
+            num_frames: Md3::read_s32(buff, section)?,
+            num_tags: Md3::read_s32(buff, section)?,
+            num_surfaces: Md3::read_s32(buff, section)?,
+            num_skins: Md3::read_s32(buff, section)?,
+            ofs_frames: Md3::read_s32(buff, section)?,
+            ofs_tags: Md3::read_s32(buff, section)?,
+            ofs_surfaces: Md3::read_s32(buff, section)?,
+            ofs_eof: Md3::read_s32(buff, section)?,
+        })
+    }
 
-        buff.set_position(surface_start + surface_header.ofs_triangles as u64);
-        let triangles = Md3::read_many(buff,
-                                       Md3::read_triangle,
-                                       surface_header.num_triangles as usize);
+    fn read_frame<R: Read + Seek>(buff: &mut Source<R>, index: usize) -> Result<Frame> {
+        let section = Section::Frame(index);
+        Ok(Frame {
+            min_bounds: Md3::read_vec3(buff, section)?,
+            max_bounds: Md3::read_vec3(buff, section)?,
+            local_origin: Md3::read_vec3(buff, section)?,
+            radius: Md3::read_f32(buff, section)?,
+            name: Md3::read_name(buff, section)?,
+        })
+    }
 
-        buff.set_position(surface_start + surface_header.ofs_st as u64);
-        let tex_coords =
-            Md3::read_many(buff, Md3::read_tex_coord, surface_header.num_verts as usize);
+    fn read_tag<R: Read + Seek>(buff: &mut Source<R>, index: usize) -> Result<Tag> {
+        let section = Section::Tag(index);
+        Ok(Tag {
+            name: Md3::read_name(buff, section)?,
+            origin: Md3::read_vec3(buff, section)?,
+            axis: Mat3::from_rows([Md3::read_vec3(buff, section)?,
+                                   Md3::read_vec3(buff, section)?,
+                                   Md3::read_vec3(buff, section)?]),
+        })
+    }
 
-        buff.set_position(surface_start + surface_header.ofs_xyznormal as u64);
-        let vertices = Md3::read_many(buff,
-                                       |x| {
-                                           Md3::read_many(x,
-                                                          Md3::read_vertex,
-                                                          surface_header.num_verts as usize)
-                                       },
-                                       surface_header.num_frames as usize);
+    fn read_surface<R: Read + Seek>(buff: &mut Source<R>,
+                                    index: usize,
+                                    surface_start: u64,
+                                    mut surface_header: SurfaceHeader,
+                                    model_frames: usize)
+                                    -> Result<Surface> {
+        let section = Section::Surface(index);
+
+        let num_frames = Md3::count(surface_header.num_frames, surface_start, section)?;
+        let num_shaders = Md3::count(surface_header.num_shaders, surface_start, section)?;
+        let num_verts = Md3::count(surface_header.num_verts, surface_start, section)?;
+        let num_triangles = Md3::count(surface_header.num_triangles, surface_start, section)?;
+
+        Md3::seek(buff, surface_start, surface_header.ofs_shaders, section)?;
+        let shaders = Md3::read_many(buff, num_shaders, |x, i| Md3::read_shader(x, index, i))?;
+
+        Md3::seek(buff, surface_start, surface_header.ofs_triangles, section)?;
+        let triangles =
+            Md3::read_many(buff, num_triangles, |x, i| Md3::read_triangle(x, index, i))?;
+
+        Md3::seek(buff, surface_start, surface_header.ofs_st, section)?;
+        let tex_coords = Md3::read_many(buff, num_verts, |x, i| Md3::read_tex_coord(x, index, i))?;
+
+        Md3::seek(buff, surface_start, surface_header.ofs_xyznormal, section)?;
+        let mut vertices = Md3::read_many(buff, num_frames.min(model_frames), |x, frame| {
+            Md3::read_many(x, num_verts, |x, i| Md3::read_vertex(x, index, frame, i))
+        })?;
+
+        if num_frames != model_frames {
+            let assumed = if num_frames > model_frames {
+                format!("ignored frames past {}", model_frames)
+            } else if num_frames == 0 {
+                format!("filled {} frames with zero vertices", model_frames)
+            } else {
+                format!("repeated frame {} up to frame {}", num_frames - 1, model_frames - 1)
+            };
+            buff.warn(Warning {
+                kind: WarningKind::FrameCountMismatch {
+                    stored: surface_header.num_frames,
+                    expected: model_frames as i32,
+                },
+                offset: surface_start,
+                section,
+                assumed,
+            });
+            let last = vertices.last().cloned().unwrap_or_else(|| {
+                vec![Vertex { x: 0, y: 0, z: 0, normal: 0 }; num_verts]
+            });
+            vertices.resize(model_frames, last);
+            surface_header.num_frames = model_frames as i32;
+        }
 
-        Surface {
+        Ok(Surface {
             header: surface_header,
-            shaders: shaders,
-            triangles: triangles,
-            tex_coords: tex_coords,
-            vertices: vertices,
-        }
+            shaders,
+            triangles,
+            tex_coords,
+            vertices,
+        })
     }
 
-    fn read_surface_header(buff: &mut Cursor<&[u8]>) -> SurfaceHeader {
-        SurfaceHeader {
-            ident: Md3::read_s32(buff),
-            name: Md3::read_string(buff, MAX_QPATH),
-            flags: Md3::read_s32(buff),
-            num_frames: Md3::read_s32(buff),
-            num_shaders: Md3::read_s32(buff),
-            num_verts: Md3::read_s32(buff),
-            num_triangles: Md3::read_s32(buff),
-            ofs_triangles: Md3::read_s32(buff),
-            ofs_shaders: Md3::read_s32(buff),
-            ofs_st: Md3::read_s32(buff),
-            ofs_xyznormal: Md3::read_s32(buff),
-            ofs_end: Md3::read_s32(buff),
-        }
+    fn read_surface_header<R: Read + Seek>(buff: &mut Source<R>,
+                                           index: usize)
+                                           -> Result<SurfaceHeader> {
+        let section = Section::Surface(index);
+        Ok(SurfaceHeader {
+            ident: Md3::read_s32(buff, section)?,
+            name: Md3::read_name(buff, section)?,
+            flags: Md3::read_s32(buff, section)?,
+            num_frames: Md3::read_s32(buff, section)?,
+            num_shaders: Md3::read_s32(buff, section)?,
+            num_verts: Md3::read_s32(buff, section)?,
+            num_triangles: Md3::read_s32(buff, section)?,
+            ofs_triangles: Md3::read_s32(buff, section)?,
+            ofs_shaders: Md3::read_s32(buff, section)?,
+            ofs_st: Md3::read_s32(buff, section)?,
+            ofs_xyznormal: Md3::read_s32(buff, section)?,
+            ofs_end: Md3::read_s32(buff, section)?,
+        })
     }
 
-    fn read_shader(buff: &mut Cursor<&[u8]>) -> Shader {
-        Shader {
-            name: Md3::read_string(buff, MAX_QPATH),
-            shader_index: Md3::read_s32(buff),
-        }
+    fn read_shader<R: Read + Seek>(buff: &mut Source<R>,
+                                   surface: usize,
+                                   index: usize)
+                                   -> Result<Shader> {
+        let section = Section::Shader { surface, index };
+        Ok(Shader {
+            name: Md3::read_name(buff, section)?,
+            shader_index: Md3::read_s32(buff, section)?,
+        })
     }
 
-    fn read_triangle(buff: &mut Cursor<&[u8]>) -> Triangle {
-        Triangle { indexes: [Md3::read_s32(buff), Md3::read_s32(buff), Md3::read_s32(buff)] }
+    fn read_triangle<R: Read + Seek>(buff: &mut Source<R>,
+                                     surface: usize,
+                                     index: usize)
+                                     -> Result<Triangle> {
+        let section = Section::Triangle { surface, index };
+        Ok(Triangle {
+            indexes: [Md3::read_s32(buff, section)?,
+                      Md3::read_s32(buff, section)?,
+                      Md3::read_s32(buff, section)?],
+        })
     }
 
-    fn read_tex_coord(buff: &mut Cursor<&[u8]>) -> TexCoord {
-        TexCoord { st: [Md3::read_f32(buff), Md3::read_f32(buff)] }
+    fn read_tex_coord<R: Read + Seek>(buff: &mut Source<R>,
+                                      surface: usize,
+                                      index: usize)
+                                      -> Result<TexCoord> {
+        let section = Section::TexCoord { surface, index };
+        Ok(TexCoord { st: [Md3::read_f32(buff, section)?, Md3::read_f32(buff, section)?] })
     }
 
-    fn read_vertex(buff: &mut Cursor<&[u8]>) -> Vertex {
-        Vertex {
-            x: Md3::read_s16(buff),
-            y: Md3::read_s16(buff),
-            z: Md3::read_s16(buff),
-            normal: Md3::read_s16(buff),
+    fn read_vertex<R: Read + Seek>(buff: &mut Source<R>,
+                                   surface: usize,
+                                   frame: usize,
+                                   index: usize)
+                                   -> Result<Vertex> {
+        let section = Section::Vertex { surface, frame, index };
+        Ok(Vertex {
+            x: Md3::read_s16(buff, section)?,
+            y: Md3::read_s16(buff, section)?,
+            z: Md3::read_s16(buff, section)?,
+            normal: Md3::read_s16(buff, section)?,
+        })
+    }
+
+    fn check_bounds<R: Read + Seek>(&self, buff: &mut Source<R>) {
+        for (i, frame) in self.frames.iter().enumerate() {
+            let (min, max) = match self.geometry_bounds(i, BoundsSource::Vertices) {
+                Some(bounds) => (bounds.min_bounds, bounds.max_bounds),
+                None => continue,
+            };
+            let (lo, hi) = (frame.min_bounds, frame.max_bounds);
+            if min.x < lo.x || min.y < lo.y || min.z < lo.z || max.x > hi.x || max.y > hi.y ||
+               max.z > hi.z {
+                buff.warn(Warning {
+                    kind: WarningKind::BoundsMismatch { frame: i },
+                    offset: self.header.ofs_frames as u64 + i as u64 * FRAME_SIZE,
+                    section: Section::Frame(i),
+                    assumed: "kept the stored bounds".to_string(),
+                });
+            }
         }
     }
 }
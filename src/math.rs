@@ -0,0 +1,615 @@
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub,
+               SubAssign};
+
+use Vec3;
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3 {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    pub fn length_squared(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    /// The unit vector in the same direction. Like `VectorNormalize`, the
+    /// zero vector is returned unchanged.
+    pub fn normalize(self) -> Vec3 {
+        let length = self.length();
+        if length > 0.0 { self / length } else { self }
+    }
+
+    /// `self` at `t = 0`, `other` at `t = 1`.
+    pub fn lerp(self, other: Vec3, t: f32) -> Vec3 {
+        self + (other - self) * t
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from(v: [f32; 3]) -> Vec3 {
+        Vec3::new(v[0], v[1], v[2])
+    }
+}
+
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, i: usize) -> &f32 {
+        match i {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index {} out of range", i),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, i: usize) -> &mut f32 {
+        match i {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("Vec3 index {} out of range", i),
+        }
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, s: f32) -> Vec3 {
+        Vec3::new(self.x * s, self.y * s, self.z * s)
+    }
+}
+
+impl Mul<Vec3> for f32 {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        v * self
+    }
+}
+
+impl Div<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn div(self, s: f32) -> Vec3 {
+        Vec3::new(self.x / s, self.y / s, self.z / s)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, other: Vec3) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign<f32> for Vec3 {
+    fn mul_assign(&mut self, s: f32) {
+        *self = *self * s;
+    }
+}
+
+impl DivAssign<f32> for Vec3 {
+    fn div_assign(&mut self, s: f32) {
+        *self = *self / s;
+    }
+}
+
+/// A 3x3 matrix stored as rows.
+///
+/// Tag axes use the Quake 3 convention: row 0 is forward, row 1 left and
+/// row 2 up, so a point `p` local to the tag lies at
+/// `origin + p.x * axis[0] + p.y * axis[1] + p.z * axis[2]`, which is
+/// `origin + axis.transpose() * p`.
+#[derive(Debug,Copy,Clone,PartialEq)]
+#[repr(C)]
+pub struct Mat3 {
+    pub rows: [Vec3; 3],
+}
+
+impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3 {
+        rows: [Vec3 {
+                   x: 1.0,
+                   y: 0.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: 0.0,
+                   y: 1.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: 0.0,
+                   y: 0.0,
+                   z: 1.0,
+               }],
+    };
+
+    pub fn from_rows(rows: [Vec3; 3]) -> Mat3 {
+        Mat3 { rows }
+    }
+
+    pub fn column(&self, i: usize) -> Vec3 {
+        Vec3::new(self.rows[0][i], self.rows[1][i], self.rows[2][i])
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        Mat3 { rows: [self.column(0), self.column(1), self.column(2)] }
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.rows[0].dot(self.rows[1].cross(self.rows[2]))
+    }
+
+    /// The inverse, or `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Mat3> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let [a, b, c] = self.rows;
+        // The columns of the inverse are the cross products of the rows.
+        let adjugate = Mat3 { rows: [b.cross(c), c.cross(a), a.cross(b)] }.transpose();
+        Some(adjugate * (1.0 / det))
+    }
+
+    /// Makes the rows unit length and perpendicular with Gram-Schmidt,
+    /// keeping the direction of row 0 and the handedness of the matrix.
+    pub fn orthonormalize(&self) -> Mat3 {
+        let [a, b, c] = self.rows;
+        let x = a.normalize();
+        let y = (b - x * x.dot(b)).normalize();
+        let z = (c - x * x.dot(c) - y * y.dot(c)).normalize();
+        Mat3 { rows: [x, y, z] }
+    }
+
+    /// Column-major 4x4 matrix placing a point local to these axes at
+    /// `origin`, ready for OpenGL style APIs.
+    pub fn to_affine(&self, origin: Vec3) -> [f32; 16] {
+        let [x, y, z] = self.rows;
+        [x.x, x.y, x.z, 0.0, y.x, y.y, y.z, 0.0, z.x, z.y, z.z, 0.0, origin.x, origin.y,
+         origin.z, 1.0]
+    }
+}
+
+impl Default for Mat3 {
+    fn default() -> Mat3 {
+        Mat3::IDENTITY
+    }
+}
+
+impl From<[Vec3; 3]> for Mat3 {
+    fn from(rows: [Vec3; 3]) -> Mat3 {
+        Mat3 { rows }
+    }
+}
+
+impl Index<usize> for Mat3 {
+    type Output = Vec3;
+
+    fn index(&self, i: usize) -> &Vec3 {
+        &self.rows[i]
+    }
+}
+
+impl IndexMut<usize> for Mat3 {
+    fn index_mut(&mut self, i: usize) -> &mut Vec3 {
+        &mut self.rows[i]
+    }
+}
+
+/// Matrix product, as `MatrixMultiply` computes it.
+impl Mul for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, other: Mat3) -> Mat3 {
+        let t = other.transpose();
+        let row = |r: Vec3| Vec3::new(r.dot(t.rows[0]), r.dot(t.rows[1]), r.dot(t.rows[2]));
+        Mat3 { rows: [row(self.rows[0]), row(self.rows[1]), row(self.rows[2])] }
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        Vec3::new(self.rows[0].dot(v), self.rows[1].dot(v), self.rows[2].dot(v))
+    }
+}
+
+impl Mul<f32> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, s: f32) -> Mat3 {
+        Mat3 { rows: [self.rows[0] * s, self.rows[1] * s, self.rows[2] * s] }
+    }
+}
+
+/// A rotation quaternion.
+///
+/// Conversions follow `Mat3`: the rotation takes the world axes onto the
+/// rows of the matrix, so `q.rotate(v) == m.transpose() * v`.
+#[derive(Debug,Copy,Clone,PartialEq)]
+#[repr(C)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    };
+
+    pub fn dot(self, other: Quat) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn normalize(self) -> Quat {
+        let length = self.dot(self).sqrt();
+        if length > 0.0 {
+            Quat {
+                x: self.x / length,
+                y: self.y / length,
+                z: self.z / length,
+                w: self.w / length,
+            }
+        } else {
+            Quat::IDENTITY
+        }
+    }
+
+    pub fn conjugate(self) -> Quat {
+        Quat {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+            w: self.w,
+        }
+    }
+
+    /// Converts a rotation matrix; `m` is orthonormalized first.
+    ///
+    /// A quaternion cannot reflect, so if `m` is left-handed (a negative
+    /// determinant, which `orthonormalize` keeps) its third row is negated
+    /// and the result is the rotation of the matching right-handed axes.
+    pub fn from_mat3(m: &Mat3) -> Quat {
+        let mut m = m.orthonormalize();
+        if m.determinant() < 0.0 {
+            m.rows[2] = -m.rows[2];
+        }
+        // Work on the conventional rotation matrix, whose columns are the axes.
+        let r = m.transpose();
+        let (m00, m11, m22) = (r[0].x, r[1].y, r[2].z);
+        let trace = m00 + m11 + m22;
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quat {
+                w: 0.25 * s,
+                x: (r[2].y - r[1].z) / s,
+                y: (r[0].z - r[2].x) / s,
+                z: (r[1].x - r[0].y) / s,
+            }
+        } else if m00 > m11 && m00 > m22 {
+            let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+            Quat {
+                w: (r[2].y - r[1].z) / s,
+                x: 0.25 * s,
+                y: (r[0].y + r[1].x) / s,
+                z: (r[0].z + r[2].x) / s,
+            }
+        } else if m11 > m22 {
+            let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+            Quat {
+                w: (r[0].z - r[2].x) / s,
+                x: (r[0].y + r[1].x) / s,
+                y: 0.25 * s,
+                z: (r[1].z + r[2].y) / s,
+            }
+        } else {
+            let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+            Quat {
+                w: (r[1].x - r[0].y) / s,
+                x: (r[0].z + r[2].x) / s,
+                y: (r[1].z + r[2].y) / s,
+                z: 0.25 * s,
+            }
+        };
+        q.normalize()
+    }
+
+    pub fn to_mat3(self) -> Mat3 {
+        let Quat { x, y, z, w } = self.normalize();
+        // Each row is the image of a world axis under the rotation.
+        Mat3 {
+            rows: [Vec3::new(1.0 - 2.0 * (y * y + z * z),
+                             2.0 * (x * y + z * w),
+                             2.0 * (x * z - y * w)),
+                   Vec3::new(2.0 * (x * y - z * w),
+                             1.0 - 2.0 * (x * x + z * z),
+                             2.0 * (y * z + x * w)),
+                   Vec3::new(2.0 * (x * z + y * w),
+                             2.0 * (y * z - x * w),
+                             1.0 - 2.0 * (x * x + y * y))],
+        }
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        let u = Vec3::new(self.x, self.y, self.z);
+        let t = u.cross(v) * 2.0;
+        v + t * self.w + u.cross(t)
+    }
+
+    /// Spherical interpolation along the shorter arc, falling back to a
+    /// normalized lerp when the rotations are nearly equal.
+    pub fn slerp(self, other: Quat, t: f32) -> Quat {
+        let mut cos = self.dot(other);
+        let mut end = other;
+        if cos < 0.0 {
+            cos = -cos;
+            end = Quat {
+                x: -other.x,
+                y: -other.y,
+                z: -other.z,
+                w: -other.w,
+            };
+        }
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+        Quat {
+            x: self.x * a + end.x * b,
+            y: self.y * a + end.y * b,
+            z: self.z * a + end.z * b,
+            w: self.w * a + end.w * b,
+        }
+        .normalize()
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Quat {
+        Quat::IDENTITY
+    }
+}
+
+/// Composes rotations: `(a * b).rotate(v) == a.rotate(b.rotate(v))`.
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, o: Quat) -> Quat {
+        Quat {
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mat3, Quat};
+    use Vec3;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_vec(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_mat(a: Mat3, b: Mat3) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).length() < EPSILON, "{:?} != {:?}", a, b);
+        }
+    }
+
+    /// The same rotation, whichever sign the quaternion has.
+    fn assert_quat(a: Quat, b: Quat) {
+        assert!(a.dot(b).abs() > 1.0 - EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    /// Axes turned `degrees` about the world `axis`, as rows.
+    fn turned(axis: usize, degrees: f32) -> Mat3 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut m = Mat3::IDENTITY;
+        m[a][a] = cos;
+        m[a][b] = sin;
+        m[b][a] = -sin;
+        m[b][b] = cos;
+        m
+    }
+
+    fn about_z(degrees: f32) -> Quat {
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        Quat {
+            x: 0.0,
+            y: 0.0,
+            z: sin,
+            w: cos,
+        }
+    }
+
+    fn general() -> Quat {
+        Quat {
+            x: 0.3,
+            y: -0.5,
+            z: 0.4,
+            w: 0.7,
+        }
+        .normalize()
+    }
+
+    #[test]
+    fn mat3_round_trips_through_every_branch() {
+        let cases = [turned(2, 30.0), // trace > 0
+                     turned(0, 180.0), // x dominant
+                     turned(1, 180.0), // y dominant
+                     turned(2, 180.0), // z dominant
+                     general().to_mat3()];
+        for m in &cases {
+            assert_mat(Quat::from_mat3(m).to_mat3(), *m);
+        }
+        assert_quat(Quat::from_mat3(&general().to_mat3()), general());
+    }
+
+    #[test]
+    fn rotation_matches_matrix() {
+        let v = Vec3::new(1.0, -2.0, 0.5);
+        for m in &[turned(0, 75.0), turned(1, -120.0), general().to_mat3()] {
+            assert_vec(Quat::from_mat3(m).rotate(v), m.transpose() * v);
+        }
+        // Turning 90 degrees about z takes forward to left.
+        assert_vec(about_z(90.0).rotate(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+        assert_mat(about_z(90.0).to_mat3(), turned(2, 90.0));
+    }
+
+    #[test]
+    fn reflected_axes_lose_the_reflection() {
+        let mut m = turned(2, 30.0);
+        m[2] = -m[2];
+        assert!(m.orthonormalize().determinant() < 0.0);
+        assert_mat(Quat::from_mat3(&m).to_mat3(), turned(2, 30.0));
+    }
+
+    #[test]
+    fn product_composes_rotations() {
+        let (a, b) = (general(), about_z(40.0));
+        let v = Vec3::new(0.25, 3.0, -1.0);
+        assert_vec((a * b).rotate(v), a.rotate(b.rotate(v)));
+        assert_mat((a * b).to_mat3(), b.to_mat3() * a.to_mat3());
+        assert_vec((a * a.conjugate()).rotate(v), v);
+    }
+
+    #[test]
+    fn slerp() {
+        let (a, b) = (Quat::IDENTITY, about_z(90.0));
+        assert_quat(a.slerp(b, 0.0), a);
+        assert_quat(a.slerp(b, 1.0), b);
+        assert_quat(a.slerp(b, 0.5), about_z(45.0));
+        assert_quat(general().slerp(general(), 0.3), general());
+    }
+
+    #[test]
+    fn slerp_takes_the_shorter_arc() {
+        let (a, b) = (Quat::IDENTITY, about_z(90.0));
+        let negated = Quat {
+            x: -b.x,
+            y: -b.y,
+            z: -b.z,
+            w: -b.w,
+        };
+        assert!(a.dot(negated) < 0.0);
+        let mid = a.slerp(negated, 0.5);
+        assert_quat(mid, about_z(45.0));
+        assert!(mid.w > 0.0);
+    }
+
+    #[test]
+    fn inverse_and_determinant() {
+        let m = Mat3::from_rows([Vec3::new(2.0, 0.5, 0.0),
+                                 Vec3::new(-1.0, 3.0, 1.0),
+                                 Vec3::new(0.0, 0.25, 4.0)]);
+        assert!((m.determinant() - 25.5).abs() < EPSILON);
+        let inverse = m.inverse().unwrap();
+        assert_mat(m * inverse, Mat3::IDENTITY);
+        assert_mat(inverse * m, Mat3::IDENTITY);
+
+        let singular = Mat3::from_rows([m[0], m[1], m[0] * 2.0]);
+        assert_eq!(singular.determinant(), 0.0);
+        assert_eq!(singular.inverse(), None);
+    }
+
+    #[test]
+    fn orthonormalize() {
+        let m = Mat3::from_rows([Vec3::new(2.0, 0.0, 0.0),
+                                 Vec3::new(1.0, 3.0, 0.0),
+                                 Vec3::new(0.5, 0.5, -0.5)]);
+        let o = m.orthonormalize();
+        assert_mat(o * o.transpose(), Mat3::IDENTITY);
+        assert_vec(o[0], Vec3::new(1.0, 0.0, 0.0));
+        assert!((o.determinant() + 1.0).abs() < EPSILON);
+        assert_mat(turned(1, 20.0).orthonormalize(), turned(1, 20.0));
+    }
+
+    #[test]
+    fn affine_places_local_points() {
+        let m = general().to_mat3();
+        let origin = Vec3::new(10.0, -4.0, 2.5);
+        let p = Vec3::new(1.0, 2.0, 3.0);
+        let a = m.to_affine(origin);
+        let column = |i: usize| Vec3::new(a[i * 4], a[i * 4 + 1], a[i * 4 + 2]);
+        let placed = column(0) * p.x + column(1) * p.y + column(2) * p.z + column(3);
+        assert_vec(placed, origin + m.transpose() * p);
+        assert_eq!([a[3], a[7], a[11], a[15]], [0.0, 0.0, 0.0, 1.0]);
+    }
+}
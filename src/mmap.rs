@@ -0,0 +1,69 @@
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+use {Md3, Md3View, Result};
+
+/// An MD3 file mapped into memory.
+///
+/// The mapping is read-only; as with any memory map, the file must not be
+/// truncated or modified by another process while it is mapped.
+pub struct MappedMd3 {
+    map: Mmap,
+}
+
+impl MappedMd3 {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MappedMd3> {
+        let file = File::open(path)?;
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MappedMd3 { map })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Validates the mapping and returns a view borrowing from it.
+    pub fn view(&self) -> Result<Md3View<'_>> {
+        Md3View::new(&self.map)
+    }
+}
+
+impl Md3 {
+    /// Loads a model through a memory map instead of reading the file.
+    pub fn from_mmap<P: AsRef<Path>>(path: P) -> Result<Md3> {
+        let mapped = MappedMd3::open(path)?;
+        Md3::from_bytes(mapped.bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use Md3;
+    use super::MappedMd3;
+    use test_model;
+
+    #[test]
+    fn mapped_file_matches_from_bytes() {
+        let bytes = test_model::padded_bytes();
+        let path = env::temp_dir().join(format!("md3_rs_mmap_{}.md3", process::id()));
+        fs::write(&path, &bytes).unwrap();
+
+        let expected = format!("{:?}", Md3::from_bytes(&bytes).unwrap());
+        let owned = Md3::from_mmap(&path).unwrap();
+        assert_eq!(format!("{:?}", owned), expected);
+
+        let mapped = MappedMd3::open(&path).unwrap();
+        assert_eq!(mapped.bytes(), &bytes[..]);
+        let view = mapped.view().unwrap();
+        assert_eq!(view.frame(1).unwrap().name(), b"frame1");
+        assert_eq!(format!("{:?}", view.to_owned().unwrap()), expected);
+
+        drop(mapped);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::str::{self, Utf8Error};
+
+/// A fixed-width, NUL-padded name stored exactly as it appears in the file.
+///
+/// The raw bytes are kept in full, including anything after the terminator,
+/// so that writing the name back reproduces the original bytes.
+#[derive(Copy,Clone,PartialEq,Eq,Hash)]
+pub struct FixedName<const N: usize> {
+    raw: [u8; N],
+}
+
+/// A `MAX_QPATH` (64 byte) name, used for models, surfaces, tags and shaders.
+pub type QPath = FixedName<64>;
+
+/// A 16 byte frame name.
+pub type FrameName = FixedName<16>;
+
+/// Error returned when a name does not fit its fixed-width field.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum NameError {
+    /// The name needs `len` bytes but at most `max` fit before the NUL.
+    TooLong { len: usize, max: usize },
+    /// The name contains a NUL byte, which would truncate it.
+    ContainsNul { at: usize },
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NameError::TooLong { len, max } => {
+                write!(f, "name is {} bytes, at most {} fit", len, max)
+            }
+            NameError::ContainsNul { at } => write!(f, "name contains NUL at byte {}", at),
+        }
+    }
+}
+
+impl error::Error for NameError {}
+
+impl<const N: usize> FixedName<N> {
+    /// Longest name that still leaves room for the NUL terminator.
+    pub const MAX_LEN: usize = N - 1;
+
+    pub fn new(name: &str) -> Result<FixedName<N>, NameError> {
+        FixedName::from_bytes(name.as_bytes())
+    }
+
+    /// Builds a NUL-padded name from `bytes`, which must fit in `N - 1` bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<FixedName<N>, NameError> {
+        if let Some(at) = bytes.iter().position(|x| *x == b'\0') {
+            return Err(NameError::ContainsNul { at });
+        }
+        if bytes.len() > Self::MAX_LEN {
+            return Err(NameError::TooLong {
+                len: bytes.len(),
+                max: Self::MAX_LEN,
+            });
+        }
+        let mut raw = [0; N];
+        raw[..bytes.len()].copy_from_slice(bytes);
+        Ok(FixedName { raw })
+    }
+
+    /// Wraps the full field exactly as stored.
+    pub fn from_raw(raw: [u8; N]) -> FixedName<N> {
+        FixedName { raw }
+    }
+
+    /// The full field, including the terminator and any bytes after it.
+    pub fn raw(&self) -> &[u8; N] {
+        &self.raw
+    }
+
+    /// The name up to the first NUL.
+    pub fn as_bytes(&self) -> &[u8] {
+        let end = self.raw.iter().position(|x| *x == b'\0').unwrap_or(N);
+        &self.raw[..end]
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the field contains a NUL, as the engine expects.
+    pub fn is_terminated(&self) -> bool {
+        self.raw.contains(&b'\0')
+    }
+
+    pub fn to_str(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(self.as_bytes())
+    }
+
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.as_bytes())
+    }
+}
+
+impl<const N: usize> Default for FixedName<N> {
+    fn default() -> FixedName<N> {
+        FixedName { raw: [0; N] }
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedName<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.to_string_lossy())
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedName<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_string_lossy())
+    }
+}
+
+impl<const N: usize> PartialEq<str> for FixedName<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a, const N: usize> PartialEq<&'a str> for FixedName<N> {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {FrameName, Md3, QPath};
+    use super::NameError;
+    use test_model::{self, FRAME_NAME};
+    use warning::WarningKind;
+
+    #[test]
+    fn new_leaves_room_for_nul() {
+        assert_eq!(FrameName::new("fifteen_bytes__").unwrap().len(), 15);
+        assert_eq!(FrameName::new("sixteen_bytes___"),
+                   Err(NameError::TooLong { len: 16, max: 15 }));
+        assert_eq!(QPath::new("a\0b"), Err(NameError::ContainsNul { at: 1 }));
+    }
+
+    #[test]
+    fn unterminated_names_load() {
+        let mut bytes = test_model::bytes();
+        bytes[FRAME_NAME..FRAME_NAME + 16].copy_from_slice(b"sixteen_bytes___");
+
+        let md3 = Md3::from_bytes(&bytes).unwrap();
+        let name = &md3.frames[0].name;
+        assert!(!name.is_terminated());
+        assert_eq!(name.as_bytes(), b"sixteen_bytes___");
+        assert_eq!(md3.to_bytes().unwrap(), bytes);
+
+        let (_, warnings) = Md3::from_bytes_lenient(&bytes).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::UnterminatedName);
+        assert_eq!(warnings[0].offset, FRAME_NAME as u64);
+    }
+}
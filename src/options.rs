@@ -0,0 +1,211 @@
+use std::fmt;
+use std::mem;
+
+use {Frame, Md3Error, Padding, Result, Section, Shader, Surface, Tag, TexCoord, Triangle,
+     Vertex};
+use validate::Layout;
+
+/// Engine limits from Quake 3's `qfiles.h`.
+pub const MD3_MAX_FRAMES: usize = 1024;
+pub const MD3_MAX_TAGS: usize = 16;
+pub const MD3_MAX_SURFACES: usize = 32;
+pub const MD3_MAX_SHADERS: usize = 256;
+pub const MD3_MAX_VERTS: usize = 4096;
+pub const MD3_MAX_TRIANGLES: usize = 8192;
+
+/// Resource caps enforced before anything is allocated for a model.
+///
+/// The defaults are the Quake 3 engine limits. Vertex, triangle and shader
+/// caps apply per surface.
+#[derive(Debug,Clone)]
+pub struct LoadOptions {
+    pub max_frames: usize,
+    pub max_tags: usize,
+    pub max_surfaces: usize,
+    pub max_shaders: usize,
+    pub max_verts: usize,
+    pub max_triangles: usize,
+    /// Upper bound on the memory the decoded `Md3` may occupy.
+    pub max_alloc_bytes: usize,
+}
+
+impl Default for LoadOptions {
+    fn default() -> LoadOptions {
+        LoadOptions {
+            max_frames: MD3_MAX_FRAMES,
+            max_tags: MD3_MAX_TAGS,
+            max_surfaces: MD3_MAX_SURFACES,
+            max_shaders: MD3_MAX_SHADERS,
+            max_verts: MD3_MAX_VERTS,
+            max_triangles: MD3_MAX_TRIANGLES,
+            max_alloc_bytes: 128 * 1024 * 1024,
+        }
+    }
+}
+
+/// The resource a `LoadOptions` cap applies to.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Limit {
+    Frames,
+    Tags,
+    Surfaces,
+    Shaders,
+    Vertices,
+    Triangles,
+    AllocatedBytes,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Limit::Frames => "frames",
+            Limit::Tags => "tags",
+            Limit::Surfaces => "surfaces",
+            Limit::Shaders => "shaders",
+            Limit::Vertices => "vertices",
+            Limit::Triangles => "triangles",
+            Limit::AllocatedBytes => "allocated bytes",
+        };
+        f.write_str(name)
+    }
+}
+
+fn limit(offset: u64, section: Section, limit: Limit, value: u64, max: usize) -> Result<()> {
+    if value > max as u64 {
+        Err(Md3Error::LimitExceeded {
+            offset,
+            section,
+            limit,
+            value,
+            max: max as u64,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn bytes<T>(count: u64) -> u64 {
+    count.saturating_mul(mem::size_of::<T>() as u64)
+}
+
+impl LoadOptions {
+    /// Checks the counts in a validated layout against every cap.
+    pub(crate) fn check(&self, layout: &Layout) -> Result<()> {
+        let header = &layout.header;
+        let section = Section::Header;
+        let num_frames = header.num_frames.max(0) as u64;
+        let num_tags = header.num_tags.max(0) as u64;
+
+        limit(0, section, Limit::Frames, num_frames, self.max_frames)?;
+        limit(0, section, Limit::Tags, num_tags, self.max_tags)?;
+        limit(0,
+              section,
+              Limit::Surfaces,
+              header.num_surfaces.max(0) as u64,
+              self.max_surfaces)?;
+
+        // Names are stored inline, so the struct sizes already count them.
+        let mut total = bytes::<Frame>(num_frames)
+            .saturating_add(bytes::<Tag>(num_frames * num_tags));
+
+        for (j, surface) in layout.surfaces.iter().enumerate() {
+            let offset = surface.offset;
+            let section = Section::Surface(j);
+            // Lenient loads pad or trim every surface to the model's frame count.
+            let surface_frames = surface.header.num_frames.max(header.num_frames).max(0) as u64;
+            let num_shaders = surface.header.num_shaders.max(0) as u64;
+            let num_verts = surface.header.num_verts.max(0) as u64;
+            let num_triangles = surface.header.num_triangles.max(0) as u64;
+
+            limit(offset, section, Limit::Shaders, num_shaders, self.max_shaders)?;
+            limit(offset, section, Limit::Vertices, num_verts, self.max_verts)?;
+            limit(offset, section, Limit::Triangles, num_triangles, self.max_triangles)?;
+
+            total = total.saturating_add(bytes::<Surface>(1))
+                .saturating_add(bytes::<Shader>(num_shaders))
+                .saturating_add(bytes::<Triangle>(num_triangles))
+                .saturating_add(bytes::<TexCoord>(num_verts))
+                .saturating_add(bytes::<Vec<Vertex>>(surface_frames))
+                .saturating_add(bytes::<Vertex>(surface_frames.saturating_mul(num_verts)));
+        }
+
+        for &(start, end) in &layout.gaps {
+            total = total.saturating_add(bytes::<Padding>(1)).saturating_add(end - start);
+        }
+
+        limit(0, section, Limit::AllocatedBytes, total, self.max_alloc_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::mem;
+
+    use {Frame, Md3, Md3Error, Section, Shader, Surface, Tag, TexCoord, Triangle, Vertex};
+    use super::{Limit, LoadOptions};
+    use test_model::{self, OFS_SURFACES};
+
+    fn limit_error(options: &LoadOptions) -> (Section, u64, Limit, u64, u64) {
+        match Md3::from_bytes_with(&test_model::bytes(), options) {
+            Err(Md3Error::LimitExceeded { section, offset, limit, value, max }) => {
+                (section, offset, limit, value, max)
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_load_the_model() {
+        Md3::from_bytes_with(&test_model::bytes(), &LoadOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn header_limits() {
+        let options = LoadOptions { max_frames: 1, ..LoadOptions::default() };
+        assert_eq!(limit_error(&options), (Section::Header, 0, Limit::Frames, 2, 1));
+        let options = LoadOptions { max_tags: 0, ..LoadOptions::default() };
+        assert_eq!(limit_error(&options), (Section::Header, 0, Limit::Tags, 1, 0));
+        let options = LoadOptions { max_surfaces: 0, ..LoadOptions::default() };
+        assert_eq!(limit_error(&options), (Section::Header, 0, Limit::Surfaces, 1, 0));
+    }
+
+    #[test]
+    fn surface_limits() {
+        let surface = test_model::get_i32(&test_model::bytes(), OFS_SURFACES) as u64;
+        let options = LoadOptions { max_shaders: 0, ..LoadOptions::default() };
+        assert_eq!(limit_error(&options),
+                   (Section::Surface(0), surface, Limit::Shaders, 1, 0));
+        let options = LoadOptions { max_verts: 2, ..LoadOptions::default() };
+        assert_eq!(limit_error(&options),
+                   (Section::Surface(0), surface, Limit::Vertices, 3, 2));
+        let options = LoadOptions { max_triangles: 0, ..LoadOptions::default() };
+        assert_eq!(limit_error(&options),
+                   (Section::Surface(0), surface, Limit::Triangles, 1, 0));
+    }
+
+    #[test]
+    fn allocation_limit() {
+        let options = LoadOptions { max_alloc_bytes: 64, ..LoadOptions::default() };
+        let (section, _, limit, value, max) = limit_error(&options);
+        assert_eq!((section, limit, max), (Section::Header, Limit::AllocatedBytes, 64));
+        assert!(value > 64);
+    }
+
+    #[test]
+    fn model_at_the_allocation_limit_loads() {
+        // Two frames with one tag each, and one surface with one shader, one
+        // triangle and three vertices in each of two frames.
+        let exact = 2 * mem::size_of::<Frame>() + 2 * mem::size_of::<Tag>() +
+                    mem::size_of::<Surface>() + mem::size_of::<Shader>() +
+                    mem::size_of::<Triangle>() + 3 * mem::size_of::<TexCoord>() +
+                    2 * mem::size_of::<Vec<Vertex>>() +
+                    6 * mem::size_of::<Vertex>();
+        let options = LoadOptions { max_alloc_bytes: exact, ..LoadOptions::default() };
+        Md3::from_reader_with(Cursor::new(test_model::bytes()), &options).unwrap();
+
+        let options = LoadOptions { max_alloc_bytes: exact - 1, ..LoadOptions::default() };
+        assert_eq!(limit_error(&options),
+                   (Section::Header, 0, Limit::AllocatedBytes, exact as u64, exact as u64 - 1));
+    }
+}
@@ -0,0 +1,347 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use {AnimationConfig, AnimationError, AnimationId, AnimationPlayer, AttachError, FrameBlend,
+     Hierarchy, Md3, Md3Error, PartId, Skin};
+
+/// Level-of-detail models beyond the base one that the renderer looks for.
+const MAX_EXTRA_LODS: usize = 2;
+
+#[derive(Debug)]
+pub enum PlayerError {
+    Io { path: PathBuf, error: io::Error },
+    Md3 { path: PathBuf, error: Md3Error },
+    Animation { path: PathBuf, error: AnimationError },
+    /// A part lacks a tag the other parts are attached to.
+    MissingTag { path: PathBuf, tag: &'static str },
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlayerError::Io { ref path, ref error } => {
+                write!(f, "{}: {}", path.display(), error)
+            }
+            PlayerError::Md3 { ref path, ref error } => {
+                write!(f, "{}: {}", path.display(), error)
+            }
+            PlayerError::Animation { ref path, ref error } => {
+                write!(f, "{}: {}", path.display(), error)
+            }
+            PlayerError::MissingTag { ref path, tag } => {
+                write!(f, "{}: missing {}", path.display(), tag)
+            }
+        }
+    }
+}
+
+impl error::Error for PlayerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            PlayerError::Io { ref error, .. } => Some(error),
+            PlayerError::Md3 { ref error, .. } => Some(error),
+            PlayerError::Animation { ref error, .. } => Some(error),
+            PlayerError::MissingTag { .. } => None,
+        }
+    }
+}
+
+/// One of the three models of a player, with its skin.
+#[derive(Debug,Clone)]
+pub struct PlayerPart {
+    /// The base model followed by the `_1` and `_2` models that exist.
+    pub lods: Vec<Md3>,
+    pub skin: Skin,
+}
+
+impl PlayerPart {
+    /// The full detail model.
+    pub fn model(&self) -> &Md3 {
+        &self.lods[0]
+    }
+
+    fn load(dir: &Path,
+            part: &str,
+            skin: &str,
+            tags: &[&'static str])
+            -> Result<PlayerPart, PlayerError> {
+        let path = dir.join(format!("{}.md3", part));
+        let base = load_md3(&path)?;
+        for tag in tags {
+            if base.tag(0, *tag).is_none() {
+                return Err(PlayerError::MissingTag {
+                    path,
+                    tag,
+                });
+            }
+        }
+
+        let mut lods = vec![base];
+        for lod in 1..MAX_EXTRA_LODS + 1 {
+            let path = dir.join(format!("{}_{}.md3", part, lod));
+            if path.is_file() {
+                lods.push(load_md3(&path)?);
+            }
+        }
+
+        let path = dir.join(format!("{}_{}.skin", part, skin));
+        let skin = Skin::parse(&read_text(&path)?);
+        Ok(PlayerPart { lods, skin })
+    }
+}
+
+fn load_md3(path: &Path) -> Result<Md3, PlayerError> {
+    Md3::from_file(path).map_err(|error| {
+        PlayerError::Md3 {
+            path: path.to_path_buf(),
+            error,
+        }
+    })
+}
+
+fn read_text(path: &Path) -> Result<String, PlayerError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Err(error) => {
+            Err(PlayerError::Io {
+                path: path.to_path_buf(),
+                error,
+            })
+        }
+    }
+}
+
+/// A player's parts joined in a `Hierarchy`, legs at the root.
+pub struct PlayerRig<'a> {
+    pub hierarchy: Hierarchy<'a>,
+    pub legs: PartId,
+    pub torso: PartId,
+    pub head: PartId,
+}
+
+/// A Quake 3 player model: legs, torso and head, their skins, and the
+/// animations from `animation.cfg`.
+///
+/// Legs and torso animate independently, as in the game.
+#[derive(Debug,Clone)]
+pub struct PlayerModel {
+    pub legs: PlayerPart,
+    pub torso: PlayerPart,
+    pub head: PlayerPart,
+    pub animations: AnimationConfig,
+    pub legs_animation: AnimationPlayer,
+    pub torso_animation: AnimationPlayer,
+}
+
+impl PlayerModel {
+    /// Loads `lower.md3`, `upper.md3` and `head.md3` with any `_1` and `_2`
+    /// level-of-detail models, the `<part>_<skin>.skin` files and
+    /// `animation.cfg` from `dir`.
+    ///
+    /// Legs start in `LEGS_IDLE` and the torso in `TORSO_STAND` at time 0.
+    pub fn load<P: AsRef<Path>>(dir: P, skin: &str) -> Result<PlayerModel, PlayerError> {
+        let dir = dir.as_ref();
+        let legs = PlayerPart::load(dir, "lower", skin, &["tag_torso"])?;
+        let torso = PlayerPart::load(dir, "upper", skin, &["tag_head", "tag_weapon"])?;
+        let head = PlayerPart::load(dir, "head", skin, &[])?;
+
+        let path = dir.join("animation.cfg");
+        let animations = match AnimationConfig::parse(&read_text(&path)?) {
+            Ok(animations) => animations,
+            Err(error) => return Err(PlayerError::Animation { path, error }),
+        };
+
+        Ok(PlayerModel {
+            legs,
+            torso,
+            head,
+            legs_animation: AnimationPlayer::new(animations[AnimationId::LegsIdle], 0),
+            torso_animation: AnimationPlayer::new(animations[AnimationId::TorsoStand], 0),
+            animations,
+        })
+    }
+
+    pub fn set_legs_animation(&mut self, id: AnimationId) {
+        self.legs_animation.set_animation(self.animations[id]);
+    }
+
+    pub fn set_torso_animation(&mut self, id: AnimationId) {
+        self.torso_animation.set_animation(self.animations[id]);
+    }
+
+    /// Advances both animations to `time`.
+    pub fn run(&mut self, time: i32, speed_scale: f32) {
+        self.legs_animation.run(time, speed_scale);
+        self.torso_animation.run(time, speed_scale);
+    }
+
+    /// Joins the full detail models at their tags, posed at the current
+    /// frames of both animations.
+    pub fn rig(&self) -> Result<PlayerRig<'_>, AttachError> {
+        let mut hierarchy = Hierarchy::new(self.legs.model());
+        let legs = hierarchy.root();
+        hierarchy.set_frames(legs, self.legs_animation.blend())?;
+        let torso = hierarchy.attach(legs, "tag_torso", self.torso.model())?;
+        hierarchy.set_frames(torso, self.torso_animation.blend())?;
+        let head = hierarchy.attach(torso, "tag_head", self.head.model())?;
+        hierarchy.set_frames(head, FrameBlend::single(0))?;
+        Ok(PlayerRig {
+            hierarchy,
+            legs,
+            torso,
+            head,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use {Mat3, Md3Builder, Orientation, SkinEntry, Vec3};
+    use super::{PlayerError, PlayerModel};
+    use test_model;
+
+    /// A quarter turn about z.
+    const TURNED: Mat3 = Mat3 {
+        rows: [Vec3 {
+                   x: 0.0,
+                   y: 1.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: -1.0,
+                   y: 0.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: 0.0,
+                   y: 0.0,
+                   z: 1.0,
+               }],
+    };
+
+    fn write_part(dir: &Path, file: &str, tags: &[(&str, Vec3, Mat3)]) {
+        let mut builder = Md3Builder::new(&format!("models/players/test/{}", file)).unwrap();
+        let frame = builder.add_frame("frame0").unwrap();
+        for &(name, origin, axis) in tags {
+            builder.add_tag(frame, name, origin, axis).unwrap();
+        }
+        fs::write(dir.join(file), builder.build().unwrap().to_bytes().unwrap()).unwrap();
+    }
+
+    /// A player whose every animation shows frame 0, with a `default` and a
+    /// `red` skin and level-of-detail models for the legs only.
+    fn write_player(name: &str) -> PathBuf {
+        let dir = test_model::temp_dir(name);
+        let torso = ("tag_torso", Vec3::new(0.0, 0.0, 20.0), Mat3::IDENTITY);
+        write_part(&dir, "lower.md3", &[torso]);
+        write_part(&dir, "lower_1.md3", &[torso]);
+        write_part(&dir, "lower_2.md3", &[torso]);
+        write_part(&dir, "upper.md3",
+                   &[("tag_head", Vec3::new(3.0, 0.0, 10.0), TURNED),
+                     ("tag_weapon", Vec3::new(8.0, 0.0, 5.0), Mat3::IDENTITY)]);
+        write_part(&dir, "head.md3", &[]);
+        for part in &["lower", "upper", "head"] {
+            for skin in &["default", "red"] {
+                fs::write(dir.join(format!("{}_{}.skin", part, skin)),
+                          format!("tag_torso,\n{}, models/players/test/{}.tga\n", part, skin))
+                    .unwrap();
+            }
+        }
+        fs::write(dir.join("animation.cfg"), "sex m\n".to_string() + &"0 1 0 20\n".repeat(25))
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn loads_parts_lods_and_skins() {
+        let dir = write_player("player_load");
+        let player = PlayerModel::load(&dir, "red").unwrap();
+        let names: Vec<String> = player.legs
+            .lods
+            .iter()
+            .map(|lod| lod.header.name.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names,
+                   ["models/players/test/lower.md3",
+                    "models/players/test/lower_1.md3",
+                    "models/players/test/lower_2.md3"]);
+        assert_eq!(player.torso.lods.len(), 1);
+        assert_eq!(player.head.lods.len(), 1);
+        assert_eq!(player.head.model().header.name, "models/players/test/head.md3");
+
+        assert_eq!(player.torso.skin.entries[1],
+                   SkinEntry::Surface {
+                       surface: "upper".to_string(),
+                       shader: "models/players/test/red.tga".to_string(),
+                   });
+        assert_eq!(player.legs.skin.shader("lower"), Some("models/players/test/red.tga"));
+        let default = PlayerModel::load(&dir, "default").unwrap();
+        assert_eq!(default.head.skin.shader("head"), Some("models/players/test/default.tga"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_tags() {
+        let dir = write_player("player_tags");
+        write_part(&dir, "upper.md3", &[("tag_weapon", Vec3::ZERO, Mat3::IDENTITY)]);
+        match PlayerModel::load(&dir, "default") {
+            Err(PlayerError::MissingTag { ref path, tag: "tag_head" }) => {
+                assert_eq!(path, &dir.join("upper.md3"))
+            }
+            other => panic!("{:?}", other),
+        }
+        write_part(&dir, "lower.md3", &[]);
+        match PlayerModel::load(&dir, "default") {
+            Err(PlayerError::MissingTag { ref path, tag: "tag_torso" }) => {
+                assert_eq!(path, &dir.join("lower.md3"))
+            }
+            other => panic!("{:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_skin() {
+        let dir = write_player("player_skin");
+        match PlayerModel::load(&dir, "blue") {
+            Err(PlayerError::Io { ref path, .. }) => {
+                assert_eq!(path, &dir.join("lower_blue.skin"))
+            }
+            other => panic!("{:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rig_places_torso_and_head() {
+        let dir = write_player("player_rig");
+        let mut player = PlayerModel::load(&dir, "default").unwrap();
+        player.run(0, 1.0);
+        let rig = player.rig().unwrap();
+        let solved = rig.hierarchy.solve().unwrap();
+
+        let legs = Orientation::default();
+        let torso = legs.attach(&Orientation {
+                                    origin: Vec3::new(0.0, 0.0, 20.0),
+                                    axis: Mat3::IDENTITY,
+                                },
+                                &Mat3::IDENTITY);
+        let head = torso.attach(&Orientation {
+                                    origin: Vec3::new(3.0, 0.0, 10.0),
+                                    axis: TURNED,
+                                },
+                                &Mat3::IDENTITY);
+        assert_eq!(solved[rig.legs.index()], legs);
+        assert_eq!(solved[rig.torso.index()], torso);
+        assert_eq!(solved[rig.head.index()], head);
+        assert_eq!(head.origin, Vec3::new(3.0, 0.0, 30.0));
+        assert_eq!(head.axis, TURNED);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
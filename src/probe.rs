@@ -0,0 +1,126 @@
+use std::io::{Read, Seek};
+
+use {Md3, Md3Header, QPath, Result, Section, SurfaceHeader};
+use source::Source;
+use validate;
+
+/// Model metadata read without decoding any vertex, triangle or texture
+/// coordinate data.
+#[derive(Debug,Clone)]
+pub struct Md3Probe {
+    pub header: Md3Header,
+    /// Tag names, taken from the first frame.
+    pub tag_names: Vec<QPath>,
+    pub surfaces: Vec<SurfaceProbe>,
+}
+
+#[derive(Debug,Clone)]
+pub struct SurfaceProbe {
+    pub header: SurfaceHeader,
+    pub shader_names: Vec<QPath>,
+}
+
+pub(crate) fn probe<R: Read + Seek>(buff: &mut Source<R>) -> Result<Md3Probe> {
+    let layout = validate::check(buff)?;
+    let header = layout.header;
+
+    let mut tag_names = Vec::new();
+    if header.num_frames > 0 {
+        Md3::seek(buff, 0, header.ofs_tags, Section::Header)?;
+        for i in 0..header.num_tags as usize {
+            tag_names.push(Md3::read_tag(buff, i)?.name);
+        }
+    }
+
+    let mut surfaces = Vec::with_capacity(layout.surfaces.len());
+    for (index, surface) in layout.surfaces.into_iter().enumerate() {
+        let offset = surface.offset;
+        let surface_header = surface.header;
+
+        Md3::seek(buff, offset, surface_header.ofs_shaders, Section::Surface(index))?;
+        let mut shader_names = Vec::new();
+        for i in 0..surface_header.num_shaders as usize {
+            shader_names.push(Md3::read_shader(buff, index, i)?.name);
+        }
+
+        surfaces.push(SurfaceProbe {
+            header: surface_header,
+            shader_names,
+        });
+    }
+
+    Ok(Md3Probe {
+        header,
+        tag_names,
+        surfaces,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+    use std::ops::Range;
+
+    use Md3;
+    use test_model;
+
+    /// A reader that fails any read overlapping `forbidden`.
+    struct Guarded {
+        inner: Cursor<Vec<u8>>,
+        forbidden: Range<u64>,
+    }
+
+    impl Read for Guarded {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let start = self.inner.position();
+            let end = start + buf.len() as u64;
+            if start < self.forbidden.end && end > self.forbidden.start {
+                return Err(io::Error::other(format!("read {}..{}", start, end)));
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for Guarded {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn matches_full_load() {
+        let bytes = test_model::bytes();
+        let probe = Md3::probe(&bytes).unwrap();
+        let md3 = Md3::from_bytes(&bytes).unwrap();
+        assert_eq!(probe.header, md3.header);
+        assert_eq!(probe.tag_names, [md3.tags[0].name]);
+        assert_eq!(probe.surfaces.len(), md3.surfaces.len());
+        for (probe, surface) in probe.surfaces.iter().zip(&md3.surfaces) {
+            assert_eq!(probe.header, surface.header);
+            let shaders: Vec<_> = surface.shaders.iter().map(|s| s.name).collect();
+            assert_eq!(probe.shader_names, shaders);
+        }
+    }
+
+    #[test]
+    fn skips_geometry() {
+        let bytes = test_model::bytes();
+        let md3 = Md3::from_bytes(&bytes).unwrap();
+        // The triangles, texture coordinates and vertices follow the shaders
+        // and fill the rest of the surface.
+        let start = md3.header.ofs_surfaces as u64;
+        let header = &md3.surfaces[0].header;
+        assert!(header.ofs_triangles > header.ofs_shaders);
+        let forbidden = start + header.ofs_triangles as u64..start + header.ofs_end as u64;
+
+        let guarded = || {
+            Guarded {
+                inner: Cursor::new(bytes.clone()),
+                forbidden: forbidden.clone(),
+            }
+        };
+        assert!(Md3::from_reader(guarded()).is_err());
+        let probe = Md3::probe_reader(guarded()).unwrap();
+        assert_eq!(probe.surfaces[0].shader_names[0], "textures/body");
+    }
+}
@@ -0,0 +1,1005 @@
+use std::error;
+use std::fmt;
+
+use {Surface, Vec3};
+use token::{Token, Tokenizer};
+
+/// Where a piece of a shader script came from. Lines and columns count
+/// from 1.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct Span {
+    /// Byte offset in the script.
+    pub offset: usize,
+    /// Length in bytes.
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl<'a, 't> From<&'t Token<'a>> for Span {
+    fn from(token: &'t Token<'a>) -> Span {
+        Span {
+            offset: token.offset,
+            len: token.text.len(),
+            line: token.line,
+            column: token.column,
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum ShaderErrorKind {
+    /// The file ends where the named item was expected.
+    UnexpectedEnd { expected: &'static str },
+    /// A shader name is not followed by `{`.
+    ExpectedBrace(String),
+    /// A `{` or `}` where a shader name was expected.
+    UnexpectedBrace,
+    /// A stage opened inside another stage.
+    NestedStage,
+    MissingArgument { keyword: String },
+    BadNumber(String),
+    /// A keyword argument that is not one of the accepted values.
+    UnknownValue { keyword: String, value: String },
+}
+
+/// A malformed shader script.
+#[derive(Debug,Clone,PartialEq)]
+pub struct ShaderError {
+    pub span: Span,
+    pub kind: ShaderErrorKind,
+}
+
+impl fmt::Display for ShaderErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderErrorKind::UnexpectedEnd { expected } => {
+                write!(f, "file ends where {} was expected", expected)
+            }
+            ShaderErrorKind::ExpectedBrace(ref token) => {
+                write!(f, "expected '{{', found '{}'", token)
+            }
+            ShaderErrorKind::UnexpectedBrace => write!(f, "expected a shader name"),
+            ShaderErrorKind::NestedStage => write!(f, "stage inside a stage"),
+            ShaderErrorKind::MissingArgument { ref keyword } => {
+                write!(f, "missing argument for '{}'", keyword)
+            }
+            ShaderErrorKind::BadNumber(ref token) => {
+                write!(f, "expected a number, found '{}'", token)
+            }
+            ShaderErrorKind::UnknownValue { ref keyword, ref value } => {
+                write!(f, "unknown '{}' value '{}'", keyword, value)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} at line {}, column {}",
+               self.kind,
+               self.span.line,
+               self.span.column)
+    }
+}
+
+impl error::Error for ShaderError {}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum WaveFunc {
+    Sin,
+    Triangle,
+    Square,
+    Sawtooth,
+    InverseSawtooth,
+    Noise,
+}
+
+/// A periodic function, as the `waveForm_t` the game parses.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct Wave {
+    pub func: WaveFunc,
+    pub base: f32,
+    pub amplitude: f32,
+    pub phase: f32,
+    pub frequency: f32,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Cull {
+    Front,
+    Back,
+    /// Both sides are drawn.
+    None,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Deform {
+    Wave { spread: f32, wave: Wave },
+    Normal { amplitude: f32, frequency: f32 },
+    Bulge { width: f32, height: f32, speed: f32 },
+    Move { vector: Vec3, wave: Wave },
+    AutoSprite,
+    AutoSprite2,
+    ProjectionShadow,
+    Text(u8),
+    /// A deform the parser does not model, with its arguments.
+    Other(Vec<String>),
+}
+
+/// A keyword that applies to the whole shader.
+#[derive(Debug,Clone,PartialEq)]
+pub enum GlobalKind {
+    Cull(Cull),
+    DeformVertexes(Deform),
+    SurfaceParm(String),
+    /// Any other keyword, such as `qer_editorimage` or `sort`.
+    Other { keyword: String, args: Vec<String> },
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Global {
+    pub kind: GlobalKind,
+    pub span: Span,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum MapSource {
+    Image(String),
+    Lightmap,
+    WhiteImage,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum BlendFactor {
+    One,
+    Zero,
+    DstColor,
+    OneMinusDstColor,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+    SrcColor,
+    OneMinusSrcColor,
+    SrcAlphaSaturate,
+}
+
+/// `blendFunc`; the `add`, `filter` and `blend` shorthands are expanded.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct BlendFunc {
+    pub src: BlendFactor,
+    pub dst: BlendFactor,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum RgbGen {
+    Identity,
+    IdentityLighting,
+    Wave(Wave),
+    Entity,
+    OneMinusEntity,
+    Vertex,
+    ExactVertex,
+    OneMinusVertex,
+    LightingDiffuse,
+    Const(Vec3),
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum TcMod {
+    Scroll { s: f32, t: f32 },
+    Scale { s: f32, t: f32 },
+    Rotate(f32),
+    Turb { base: f32, amplitude: f32, phase: f32, frequency: f32 },
+    Stretch(Wave),
+    Transform { matrix: [[f32; 2]; 2], translate: [f32; 2] },
+    EntityTranslate,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum AlphaFunc {
+    Gt0,
+    Lt128,
+    Ge128,
+}
+
+/// A keyword inside a stage.
+#[derive(Debug,Clone,PartialEq)]
+pub enum StageKind {
+    Map(MapSource),
+    ClampMap(String),
+    AnimMap { frequency: f32, images: Vec<String> },
+    BlendFunc(BlendFunc),
+    RgbGen(RgbGen),
+    TcMod(TcMod),
+    AlphaFunc(AlphaFunc),
+    /// Any other keyword, such as `alphaGen` or `depthWrite`.
+    Other { keyword: String, args: Vec<String> },
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct StageDirective {
+    pub kind: StageKind,
+    pub span: Span,
+}
+
+/// One `{ ... }` pass of a shader.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Stage {
+    pub directives: Vec<StageDirective>,
+    /// The opening brace.
+    pub span: Span,
+}
+
+impl Stage {
+    /// The first image the stage draws, if any.
+    pub fn image(&self) -> Option<&str> {
+        self.directives.iter().filter_map(|d| match d.kind {
+            StageKind::Map(MapSource::Image(ref image)) |
+            StageKind::ClampMap(ref image) => Some(&image[..]),
+            StageKind::AnimMap { ref images, .. } => images.first().map(|i| &i[..]),
+            _ => None,
+        }).next()
+    }
+
+    pub fn blend_func(&self) -> Option<BlendFunc> {
+        self.directives.iter().filter_map(|d| match d.kind {
+            StageKind::BlendFunc(blend) => Some(blend),
+            _ => None,
+        }).next()
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct ShaderDef {
+    pub name: String,
+    pub globals: Vec<Global>,
+    pub stages: Vec<Stage>,
+    /// The name.
+    pub span: Span,
+}
+
+impl ShaderDef {
+    /// The texture that best stands for the shader's surface color.
+    ///
+    /// This is the image of the first stage that is not additive, so glow
+    /// and environment passes are skipped, falling back to the first image
+    /// of any stage and then to `qer_editorimage`.
+    pub fn diffuse_texture(&self) -> Option<&str> {
+        let additive = BlendFunc {
+            src: BlendFactor::One,
+            dst: BlendFactor::One,
+        };
+        self.stages
+            .iter()
+            .filter(|stage| stage.blend_func() != Some(additive))
+            .filter_map(Stage::image)
+            .next()
+            .or_else(|| self.stages.iter().filter_map(Stage::image).next())
+            .or_else(|| {
+                self.globals.iter().filter_map(|g| match g.kind {
+                    GlobalKind::Other { ref keyword, ref args }
+                        if keyword.eq_ignore_ascii_case("qer_editorimage") => {
+                        args.first().map(|a| &a[..])
+                    }
+                    _ => None,
+                }).next()
+            })
+    }
+}
+
+/// A parsed `.shader` file.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct ShaderScript {
+    pub shaders: Vec<ShaderDef>,
+}
+
+fn missing(keyword: &Token) -> ShaderError {
+    ShaderError {
+        span: keyword.into(),
+        kind: ShaderErrorKind::MissingArgument { keyword: keyword.text.to_string() },
+    }
+}
+
+fn unknown(keyword: &Token, value: &Token) -> ShaderError {
+    ShaderError {
+        span: value.into(),
+        kind: ShaderErrorKind::UnknownValue {
+            keyword: keyword.text.to_string(),
+            value: value.text.to_string(),
+        },
+    }
+}
+
+fn is_brace(token: &Token) -> bool {
+    token.text == "{" || token.text == "}"
+}
+
+fn blend_factor(keyword: &Token, value: &Token) -> Result<BlendFactor, ShaderError> {
+    let factor = match &value.text.to_ascii_uppercase()[..] {
+        "GL_ONE" => BlendFactor::One,
+        "GL_ZERO" => BlendFactor::Zero,
+        "GL_DST_COLOR" => BlendFactor::DstColor,
+        "GL_ONE_MINUS_DST_COLOR" => BlendFactor::OneMinusDstColor,
+        "GL_SRC_ALPHA" => BlendFactor::SrcAlpha,
+        "GL_ONE_MINUS_SRC_ALPHA" => BlendFactor::OneMinusSrcAlpha,
+        "GL_DST_ALPHA" => BlendFactor::DstAlpha,
+        "GL_ONE_MINUS_DST_ALPHA" => BlendFactor::OneMinusDstAlpha,
+        "GL_SRC_COLOR" => BlendFactor::SrcColor,
+        "GL_ONE_MINUS_SRC_COLOR" => BlendFactor::OneMinusSrcColor,
+        "GL_SRC_ALPHA_SATURATE" => BlendFactor::SrcAlphaSaturate,
+        _ => return Err(unknown(keyword, value)),
+    };
+    Ok(factor)
+}
+
+/// Reads the keywords of one shader, one line at a time.
+struct Parser<'a> {
+    tokens: Tokenizer<'a>,
+}
+
+impl<'a> Parser<'a> {
+    /// The next argument of `keyword` on the same line.
+    fn arg(&mut self, keyword: &Token) -> Result<Token<'a>, ShaderError> {
+        match self.tokens.peek() {
+            Some(ref token) if is_brace(token) => Err(missing(keyword)),
+            _ => self.tokens.next_on_line().ok_or_else(|| missing(keyword)),
+        }
+    }
+
+    fn float(&mut self, keyword: &Token) -> Result<f32, ShaderError> {
+        let token = self.arg(keyword)?;
+        token.text.parse().map_err(|_| {
+            ShaderError {
+                span: (&token).into(),
+                kind: ShaderErrorKind::BadNumber(token.text.to_string()),
+            }
+        })
+    }
+
+    /// The remaining arguments on the line, stopping at a brace.
+    fn rest(&mut self) -> Vec<String> {
+        let mut args = Vec::new();
+        while let Some(token) = self.tokens.peek() {
+            if is_brace(&token) {
+                break;
+            }
+            match self.tokens.next_on_line() {
+                Some(token) => args.push(token.text.to_string()),
+                None => break,
+            }
+        }
+        args
+    }
+
+    /// Drops any arguments left after a keyword. A brace is left alone, so
+    /// that `{ map $lightmap }` and `cull none }` close where they should.
+    fn skip_extra(&mut self) {
+        self.rest();
+    }
+
+    fn wave(&mut self, keyword: &Token) -> Result<Wave, ShaderError> {
+        let name = self.arg(keyword)?;
+        let func = match &name.text.to_ascii_lowercase()[..] {
+            "sin" => WaveFunc::Sin,
+            "triangle" => WaveFunc::Triangle,
+            "square" => WaveFunc::Square,
+            "sawtooth" => WaveFunc::Sawtooth,
+            "inversesawtooth" => WaveFunc::InverseSawtooth,
+            "noise" => WaveFunc::Noise,
+            _ => return Err(unknown(keyword, &name)),
+        };
+        Ok(Wave {
+            func,
+            base: self.float(keyword)?,
+            amplitude: self.float(keyword)?,
+            phase: self.float(keyword)?,
+            frequency: self.float(keyword)?,
+        })
+    }
+
+    fn global(&mut self, keyword: &Token) -> Result<GlobalKind, ShaderError> {
+        let kind = match &keyword.text.to_ascii_lowercase()[..] {
+            "cull" => {
+                let value = self.arg(keyword)?;
+                GlobalKind::Cull(match &value.text.to_ascii_lowercase()[..] {
+                    "front" => Cull::Front,
+                    "back" | "backside" | "backsided" => Cull::Back,
+                    "none" | "twosided" | "disable" => Cull::None,
+                    _ => return Err(unknown(keyword, &value)),
+                })
+            }
+            "surfaceparm" => GlobalKind::SurfaceParm(self.arg(keyword)?.text.to_string()),
+            "deformvertexes" => GlobalKind::DeformVertexes(self.deform(keyword)?),
+            _ => {
+                GlobalKind::Other {
+                    keyword: keyword.text.to_string(),
+                    args: self.rest(),
+                }
+            }
+        };
+        Ok(kind)
+    }
+
+    fn deform(&mut self, keyword: &Token) -> Result<Deform, ShaderError> {
+        let kind = self.arg(keyword)?;
+        let lower = kind.text.to_ascii_lowercase();
+        let deform = match &lower[..] {
+            "wave" => {
+                Deform::Wave {
+                    spread: self.float(keyword)?,
+                    wave: self.wave(keyword)?,
+                }
+            }
+            "normal" => {
+                Deform::Normal {
+                    amplitude: self.float(keyword)?,
+                    frequency: self.float(keyword)?,
+                }
+            }
+            "bulge" => {
+                Deform::Bulge {
+                    width: self.float(keyword)?,
+                    height: self.float(keyword)?,
+                    speed: self.float(keyword)?,
+                }
+            }
+            "move" => {
+                Deform::Move {
+                    vector: Vec3::new(self.float(keyword)?,
+                                      self.float(keyword)?,
+                                      self.float(keyword)?),
+                    wave: self.wave(keyword)?,
+                }
+            }
+            "autosprite" => Deform::AutoSprite,
+            "autosprite2" => Deform::AutoSprite2,
+            "projectionshadow" => Deform::ProjectionShadow,
+            _ if lower.len() == 5 && lower.starts_with("text") &&
+                 lower.as_bytes()[4].is_ascii_digit() => Deform::Text(lower.as_bytes()[4] - b'0'),
+            _ => {
+                let mut args = vec![kind.text.to_string()];
+                args.extend(self.rest());
+                Deform::Other(args)
+            }
+        };
+        Ok(deform)
+    }
+
+    fn stage_directive(&mut self, keyword: &Token) -> Result<StageKind, ShaderError> {
+        let kind = match &keyword.text.to_ascii_lowercase()[..] {
+            "map" => {
+                let value = self.arg(keyword)?;
+                StageKind::Map(match &value.text.to_ascii_lowercase()[..] {
+                    "$lightmap" => MapSource::Lightmap,
+                    "$whiteimage" | "*white" => MapSource::WhiteImage,
+                    _ => MapSource::Image(value.text.to_string()),
+                })
+            }
+            "clampmap" => StageKind::ClampMap(self.arg(keyword)?.text.to_string()),
+            "animmap" => {
+                let frequency = self.float(keyword)?;
+                let images = self.rest();
+                if images.is_empty() {
+                    return Err(missing(keyword));
+                }
+                StageKind::AnimMap { frequency, images }
+            }
+            "blendfunc" => {
+                let first = self.arg(keyword)?;
+                let shorthand = match &first.text.to_ascii_lowercase()[..] {
+                    "add" => Some((BlendFactor::One, BlendFactor::One)),
+                    "filter" => Some((BlendFactor::DstColor, BlendFactor::Zero)),
+                    "blend" => Some((BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha)),
+                    _ => None,
+                };
+                let (src, dst) = match shorthand {
+                    Some(factors) => factors,
+                    None => {
+                        let second = self.arg(keyword)?;
+                        (blend_factor(keyword, &first)?, blend_factor(keyword, &second)?)
+                    }
+                };
+                StageKind::BlendFunc(BlendFunc { src, dst })
+            }
+            "rgbgen" => StageKind::RgbGen(self.rgb_gen(keyword)?),
+            "tcmod" => StageKind::TcMod(self.tc_mod(keyword)?),
+            "alphafunc" => {
+                let value = self.arg(keyword)?;
+                StageKind::AlphaFunc(match &value.text.to_ascii_uppercase()[..] {
+                    "GT0" => AlphaFunc::Gt0,
+                    "LT128" => AlphaFunc::Lt128,
+                    "GE128" => AlphaFunc::Ge128,
+                    _ => return Err(unknown(keyword, &value)),
+                })
+            }
+            _ => {
+                StageKind::Other {
+                    keyword: keyword.text.to_string(),
+                    args: self.rest(),
+                }
+            }
+        };
+        Ok(kind)
+    }
+
+    fn rgb_gen(&mut self, keyword: &Token) -> Result<RgbGen, ShaderError> {
+        let value = self.arg(keyword)?;
+        let rgb_gen = match &value.text.to_ascii_lowercase()[..] {
+            "identity" => RgbGen::Identity,
+            "identitylighting" => RgbGen::IdentityLighting,
+            "wave" => RgbGen::Wave(self.wave(keyword)?),
+            "entity" => RgbGen::Entity,
+            "oneminusentity" => RgbGen::OneMinusEntity,
+            "vertex" => RgbGen::Vertex,
+            "exactvertex" => RgbGen::ExactVertex,
+            "oneminusvertex" => RgbGen::OneMinusVertex,
+            "lightingdiffuse" => RgbGen::LightingDiffuse,
+            "const" | "constant" => {
+                // The color is written `( r g b )`.
+                let open = self.arg(keyword)?;
+                if open.text != "(" {
+                    return Err(unknown(keyword, &open));
+                }
+                let color = Vec3::new(self.float(keyword)?,
+                                      self.float(keyword)?,
+                                      self.float(keyword)?);
+                let close = self.arg(keyword)?;
+                if close.text != ")" {
+                    return Err(unknown(keyword, &close));
+                }
+                RgbGen::Const(color)
+            }
+            _ => return Err(unknown(keyword, &value)),
+        };
+        Ok(rgb_gen)
+    }
+
+    fn tc_mod(&mut self, keyword: &Token) -> Result<TcMod, ShaderError> {
+        let value = self.arg(keyword)?;
+        let tc_mod = match &value.text.to_ascii_lowercase()[..] {
+            "scroll" => {
+                TcMod::Scroll {
+                    s: self.float(keyword)?,
+                    t: self.float(keyword)?,
+                }
+            }
+            "scale" => {
+                TcMod::Scale {
+                    s: self.float(keyword)?,
+                    t: self.float(keyword)?,
+                }
+            }
+            "rotate" => TcMod::Rotate(self.float(keyword)?),
+            "turb" => {
+                TcMod::Turb {
+                    base: self.float(keyword)?,
+                    amplitude: self.float(keyword)?,
+                    phase: self.float(keyword)?,
+                    frequency: self.float(keyword)?,
+                }
+            }
+            "stretch" => TcMod::Stretch(self.wave(keyword)?),
+            "transform" => {
+                TcMod::Transform {
+                    matrix: [[self.float(keyword)?, self.float(keyword)?],
+                             [self.float(keyword)?, self.float(keyword)?]],
+                    translate: [self.float(keyword)?, self.float(keyword)?],
+                }
+            }
+            "entitytranslate" => TcMod::EntityTranslate,
+            _ => return Err(unknown(keyword, &value)),
+        };
+        Ok(tc_mod)
+    }
+
+    fn stage(&mut self, open: &Token) -> Result<Stage, ShaderError> {
+        let mut directives = Vec::new();
+        loop {
+            let token = match self.tokens.next() {
+                Some(token) => token,
+                None => return Err(self.end("'}'")),
+            };
+            match token.text {
+                "}" => break,
+                "{" => {
+                    return Err(ShaderError {
+                        span: (&token).into(),
+                        kind: ShaderErrorKind::NestedStage,
+                    })
+                }
+                _ => {
+                    let kind = self.stage_directive(&token)?;
+                    self.skip_extra();
+                    directives.push(StageDirective {
+                        kind,
+                        span: (&token).into(),
+                    });
+                }
+            }
+        }
+        Ok(Stage {
+            directives,
+            span: open.into(),
+        })
+    }
+
+    fn end(&self, expected: &'static str) -> ShaderError {
+        let (line, column) = self.tokens.location();
+        ShaderError {
+            span: Span {
+                offset: self.tokens.offset(),
+                len: 0,
+                line,
+                column,
+            },
+            kind: ShaderErrorKind::UnexpectedEnd { expected },
+        }
+    }
+}
+
+impl ShaderScript {
+    /// Parses every shader in a `.shader` file.
+    ///
+    /// Arguments are read from the keyword's line, as the game reads them.
+    /// Keywords the AST does not model are kept as `Other`; the modelled
+    /// ones are checked and fail with the span of the offending token.
+    pub fn parse(text: &str) -> Result<ShaderScript, ShaderError> {
+        let mut parser = Parser { tokens: Tokenizer::new(text) };
+        let mut shaders = Vec::new();
+        while let Some(name) = parser.tokens.next() {
+            if is_brace(&name) {
+                return Err(ShaderError {
+                    span: (&name).into(),
+                    kind: ShaderErrorKind::UnexpectedBrace,
+                });
+            }
+            match parser.tokens.next() {
+                Some(ref open) if open.text == "{" => {}
+                Some(other) => {
+                    return Err(ShaderError {
+                        span: (&other).into(),
+                        kind: ShaderErrorKind::ExpectedBrace(other.text.to_string()),
+                    })
+                }
+                None => return Err(parser.end("'{'")),
+            }
+
+            let mut globals = Vec::new();
+            let mut stages = Vec::new();
+            loop {
+                let token = match parser.tokens.next() {
+                    Some(token) => token,
+                    None => return Err(parser.end("'}'")),
+                };
+                match token.text {
+                    "}" => break,
+                    "{" => stages.push(parser.stage(&token)?),
+                    _ => {
+                        let kind = parser.global(&token)?;
+                        parser.skip_extra();
+                        globals.push(Global {
+                            kind,
+                            span: (&token).into(),
+                        });
+                    }
+                }
+            }
+            shaders.push(ShaderDef {
+                name: name.text.to_string(),
+                globals,
+                stages,
+                span: (&name).into(),
+            });
+        }
+        Ok(ShaderScript { shaders })
+    }
+
+    /// The shader named `name`, compared as the game does: without regard
+    /// to case and ignoring any file extension on `name`.
+    pub fn find(&self, name: &str) -> Option<&ShaderDef> {
+        let name = strip_extension(name);
+        self.shaders.iter().find(|shader| shader.name.eq_ignore_ascii_case(name))
+    }
+}
+
+fn strip_extension(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(dot) if !name[dot..].contains('/') => &name[..dot],
+        _ => name,
+    }
+}
+
+/// Shader scripts gathered from several files, for looking up the
+/// textures a model's shaders draw.
+#[derive(Debug,Clone,Default)]
+pub struct ShaderLibrary {
+    scripts: Vec<ShaderScript>,
+}
+
+impl ShaderLibrary {
+    pub fn new() -> ShaderLibrary {
+        ShaderLibrary::default()
+    }
+
+    /// Adds a script. When a shader is defined more than once, the script
+    /// added first wins.
+    pub fn add(&mut self, script: ShaderScript) -> &mut ShaderLibrary {
+        self.scripts.push(script);
+        self
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ShaderDef> {
+        self.scripts.iter().filter_map(|script| script.find(name)).next()
+    }
+
+    /// The diffuse texture path for a shader name.
+    ///
+    /// A defined shader gives its `diffuse_texture`. A name with no
+    /// definition is an image path itself, as the game treats it; the game
+    /// tries it with a `.tga` and then a `.jpg` extension.
+    pub fn diffuse_texture<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        match self.find(name) {
+            Some(shader) => shader.diffuse_texture(),
+            None if name.is_empty() => None,
+            None => Some(name),
+        }
+    }
+
+    /// The diffuse texture of a surface's first shader. Pass the shader a
+    /// skin gives the surface to `diffuse_texture` instead when there is one.
+    pub fn surface_texture<'a>(&'a self, surface: &'a Surface) -> Option<&'a str> {
+        let shader = surface.shaders.first()?;
+        self.diffuse_texture(shader.name.to_str().ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Vec3;
+    use super::*;
+
+    fn parse_one(text: &str) -> ShaderDef {
+        let mut script = ShaderScript::parse(text).unwrap();
+        assert_eq!(script.shaders.len(), 1);
+        script.shaders.remove(0)
+    }
+
+    fn globals(shader: &ShaderDef) -> Vec<GlobalKind> {
+        shader.globals.iter().map(|g| g.kind.clone()).collect()
+    }
+
+    fn directives(stage: &Stage) -> Vec<StageKind> {
+        stage.directives.iter().map(|d| d.kind.clone()).collect()
+    }
+
+    fn error(text: &str) -> ShaderError {
+        ShaderScript::parse(text).unwrap_err()
+    }
+
+    fn span(offset: usize, len: usize, line: usize, column: usize) -> Span {
+        Span {
+            offset,
+            len,
+            line,
+            column,
+        }
+    }
+
+    fn wave(func: WaveFunc, base: f32, amplitude: f32, phase: f32, frequency: f32) -> Wave {
+        Wave {
+            func,
+            base,
+            amplitude,
+            phase,
+            frequency,
+        }
+    }
+
+    #[test]
+    fn one_line_stages() {
+        let shader = parse_one("textures/a\n{\n{ map $lightmap }\n\
+                                { map a.tga blendFunc add }\n}\n");
+        assert_eq!(shader.stages.len(), 2);
+        assert_eq!(directives(&shader.stages[0]), vec![StageKind::Map(MapSource::Lightmap)]);
+        // Whatever follows a keyword's arguments on the line is ignored.
+        assert_eq!(directives(&shader.stages[1]),
+                   vec![StageKind::Map(MapSource::Image("a.tga".to_string()))]);
+    }
+
+    #[test]
+    fn closing_brace_after_a_keyword() {
+        let script = ShaderScript::parse("textures/a\n{\ncull none }\n\
+                                          textures/b\n{\n{\nmap b.tga }\n}\n")
+            .unwrap();
+        assert_eq!(script.shaders.len(), 2);
+        assert_eq!(globals(&script.shaders[0]), vec![GlobalKind::Cull(Cull::None)]);
+        assert!(script.shaders[0].stages.is_empty());
+        assert_eq!(script.shaders[1].stages[0].image(), Some("b.tga"));
+    }
+
+    #[test]
+    fn brace_on_the_name_line() {
+        let script = ShaderScript::parse("textures/a {\nsurfaceparm nodraw\n}\n\
+                                          textures/b { qer_editorimage b.tga }\n")
+            .unwrap();
+        let names: Vec<&str> = script.shaders.iter().map(|s| &s.name[..]).collect();
+        assert_eq!(names, ["textures/a", "textures/b"]);
+        assert_eq!(globals(&script.shaders[0]),
+                   vec![GlobalKind::SurfaceParm("nodraw".to_string())]);
+        assert_eq!(script.shaders[1].diffuse_texture(), Some("b.tga"));
+        assert_eq!(script.shaders[1].span, span(34, 10, 4, 1));
+    }
+
+    #[test]
+    fn global_keywords() {
+        let shader = parse_one("textures/a\n{\n\
+                                cull front\n\
+                                cull backsided\n\
+                                cull twosided\n\
+                                surfaceparm trans\n\
+                                deformVertexes wave 100 sin 0 1 0.5 2\n\
+                                deformVertexes normal 0.5 3\n\
+                                deformVertexes bulge 1 2 3\n\
+                                deformVertexes move 0 0 1 triangle 0 1 0 0.25\n\
+                                deformVertexes autoSprite\n\
+                                deformVertexes autoSprite2\n\
+                                deformVertexes projectionShadow\n\
+                                deformVertexes text3\n\
+                                deformVertexes flap 1 2\n\
+                                sort additive\n\
+                                }\n");
+        assert_eq!(globals(&shader),
+                   vec![GlobalKind::Cull(Cull::Front),
+                        GlobalKind::Cull(Cull::Back),
+                        GlobalKind::Cull(Cull::None),
+                        GlobalKind::SurfaceParm("trans".to_string()),
+                        GlobalKind::DeformVertexes(Deform::Wave {
+                            spread: 100.0,
+                            wave: wave(WaveFunc::Sin, 0.0, 1.0, 0.5, 2.0),
+                        }),
+                        GlobalKind::DeformVertexes(Deform::Normal {
+                            amplitude: 0.5,
+                            frequency: 3.0,
+                        }),
+                        GlobalKind::DeformVertexes(Deform::Bulge {
+                            width: 1.0,
+                            height: 2.0,
+                            speed: 3.0,
+                        }),
+                        GlobalKind::DeformVertexes(Deform::Move {
+                            vector: Vec3::new(0.0, 0.0, 1.0),
+                            wave: wave(WaveFunc::Triangle, 0.0, 1.0, 0.0, 0.25),
+                        }),
+                        GlobalKind::DeformVertexes(Deform::AutoSprite),
+                        GlobalKind::DeformVertexes(Deform::AutoSprite2),
+                        GlobalKind::DeformVertexes(Deform::ProjectionShadow),
+                        GlobalKind::DeformVertexes(Deform::Text(3)),
+                        GlobalKind::DeformVertexes(Deform::Other(vec!["flap".to_string(),
+                                                                      "1".to_string(),
+                                                                      "2".to_string()])),
+                        GlobalKind::Other {
+                            keyword: "sort".to_string(),
+                            args: vec!["additive".to_string()],
+                        }]);
+    }
+
+    #[test]
+    fn stage_keywords() {
+        let shader = parse_one("textures/a\n{\n{\n\
+                                map $whiteimage\n\
+                                clampMap c.tga\n\
+                                animMap 10 a1.tga a2.tga\n\
+                                blendFunc filter\n\
+                                blendFunc blend\n\
+                                blendFunc GL_DST_COLOR GL_ONE_MINUS_SRC_ALPHA\n\
+                                rgbGen identityLighting\n\
+                                rgbGen wave square 0 1 0 4\n\
+                                rgbGen const ( 0.5 0.25 1 )\n\
+                                rgbGen lightingDiffuse\n\
+                                tcMod scroll 1 -1\n\
+                                tcMod scale 2 2\n\
+                                tcMod rotate 30\n\
+                                tcMod turb 0 0.1 0 1\n\
+                                tcMod stretch sawtooth 1 0.5 0 2\n\
+                                tcMod transform 1 0 0 1 0.5 0\n\
+                                tcMod entityTranslate\n\
+                                alphaFunc GE128\n\
+                                alphaGen vertex\n\
+                                }\n}\n");
+        assert_eq!(directives(&shader.stages[0]),
+                   vec![StageKind::Map(MapSource::WhiteImage),
+                        StageKind::ClampMap("c.tga".to_string()),
+                        StageKind::AnimMap {
+                            frequency: 10.0,
+                            images: vec!["a1.tga".to_string(), "a2.tga".to_string()],
+                        },
+                        StageKind::BlendFunc(BlendFunc {
+                            src: BlendFactor::DstColor,
+                            dst: BlendFactor::Zero,
+                        }),
+                        StageKind::BlendFunc(BlendFunc {
+                            src: BlendFactor::SrcAlpha,
+                            dst: BlendFactor::OneMinusSrcAlpha,
+                        }),
+                        StageKind::BlendFunc(BlendFunc {
+                            src: BlendFactor::DstColor,
+                            dst: BlendFactor::OneMinusSrcAlpha,
+                        }),
+                        StageKind::RgbGen(RgbGen::IdentityLighting),
+                        StageKind::RgbGen(RgbGen::Wave(wave(WaveFunc::Square, 0.0, 1.0, 0.0, 4.0))),
+                        StageKind::RgbGen(RgbGen::Const(Vec3::new(0.5, 0.25, 1.0))),
+                        StageKind::RgbGen(RgbGen::LightingDiffuse),
+                        StageKind::TcMod(TcMod::Scroll { s: 1.0, t: -1.0 }),
+                        StageKind::TcMod(TcMod::Scale { s: 2.0, t: 2.0 }),
+                        StageKind::TcMod(TcMod::Rotate(30.0)),
+                        StageKind::TcMod(TcMod::Turb {
+                            base: 0.0,
+                            amplitude: 0.1,
+                            phase: 0.0,
+                            frequency: 1.0,
+                        }),
+                        StageKind::TcMod(TcMod::Stretch(wave(WaveFunc::Sawtooth,
+                                                             1.0,
+                                                             0.5,
+                                                             0.0,
+                                                             2.0))),
+                        StageKind::TcMod(TcMod::Transform {
+                            matrix: [[1.0, 0.0], [0.0, 1.0]],
+                            translate: [0.5, 0.0],
+                        }),
+                        StageKind::TcMod(TcMod::EntityTranslate),
+                        StageKind::AlphaFunc(AlphaFunc::Ge128),
+                        StageKind::Other {
+                            keyword: "alphaGen".to_string(),
+                            args: vec!["vertex".to_string()],
+                        }]);
+        assert_eq!(shader.stages[0].span, span(13, 1, 3, 1));
+        assert_eq!(shader.stages[0].directives[1].span, span(31, 8, 5, 1));
+    }
+
+    #[test]
+    fn error_spans() {
+        let err = error("a\n{\n{\nmap a.tga\n{\n");
+        assert_eq!(err.kind, ShaderErrorKind::NestedStage);
+        assert_eq!(err.span, span(16, 1, 5, 1));
+
+        let err = error("a\nsort 1\n");
+        assert_eq!(err.kind, ShaderErrorKind::ExpectedBrace("sort".to_string()));
+        assert_eq!(err.span, span(2, 4, 2, 1));
+
+        let err = error("}");
+        assert_eq!(err.kind, ShaderErrorKind::UnexpectedBrace);
+        assert_eq!(err.span, span(0, 1, 1, 1));
+
+        let err = error("a { cull\n}");
+        assert_eq!(err.kind, ShaderErrorKind::MissingArgument { keyword: "cull".to_string() });
+        assert_eq!(err.span, span(4, 4, 1, 5));
+
+        let err = error("a { { map } }");
+        assert_eq!(err.kind, ShaderErrorKind::MissingArgument { keyword: "map".to_string() });
+        assert_eq!(err.span, span(6, 3, 1, 7));
+
+        let err = error("a {\n{\n  tcMod scroll 1 x\n}\n}");
+        assert_eq!(err.kind, ShaderErrorKind::BadNumber("x".to_string()));
+        assert_eq!(err.span, span(23, 1, 3, 18));
+
+        let err = error("a {\ncull sideways\n}");
+        assert_eq!(err.kind,
+                   ShaderErrorKind::UnknownValue {
+                       keyword: "cull".to_string(),
+                       value: "sideways".to_string(),
+                   });
+        assert_eq!(err.span, span(9, 8, 2, 6));
+
+        let err = error("a {\n{\nmap a.tga\n");
+        assert_eq!(err.kind, ShaderErrorKind::UnexpectedEnd { expected: "'}'" });
+        assert_eq!(err.span, span(16, 0, 4, 1));
+
+        let err = error("a");
+        assert_eq!(err.kind, ShaderErrorKind::UnexpectedEnd { expected: "'{'" });
+        assert_eq!(err.span, span(1, 0, 1, 2));
+    }
+}
@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use Md3;
+use token::Tokenizer;
+
+/// One line of a `.skin` file.
+#[derive(Debug,Clone,PartialEq)]
+pub enum SkinEntry {
+    /// Draws the surface named `surface` with `shader`.
+    Surface { surface: String, shader: String },
+    /// A `tag_*` line. The game ignores these.
+    Tag(String),
+}
+
+/// A parsed `.skin` file, mapping surface names to shaders.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Skin {
+    pub entries: Vec<SkinEntry>,
+}
+
+impl Skin {
+    /// Parses a `.skin` file as `RE_RegisterSkin` does. Surface names are
+    /// lowercased; anything malformed is read the way the game reads it, so
+    /// parsing never fails.
+    pub fn parse(text: &str) -> Skin {
+        let mut tokens = Tokenizer::with_commas(text);
+        let mut entries = Vec::new();
+        while let Some(token) = tokens.next() {
+            if token.text.is_empty() {
+                break;
+            }
+            let surface = token.text.to_ascii_lowercase();
+            tokens.eat(b',');
+            if token.text.contains("tag_") {
+                entries.push(SkinEntry::Tag(surface));
+                continue;
+            }
+            let shader = tokens.next().map_or("", |t| t.text);
+            entries.push(SkinEntry::Surface {
+                surface,
+                shader: shader.to_string(),
+            });
+        }
+        Skin { entries }
+    }
+
+    /// The shader the skin gives `surface`, compared without regard to case.
+    /// The first matching line wins.
+    pub fn shader(&self, surface: &str) -> Option<&str> {
+        for entry in &self.entries {
+            if let SkinEntry::Surface { surface: ref name, ref shader } = *entry {
+                if name.eq_ignore_ascii_case(surface) {
+                    return Some(shader);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Quotes a token if `CommaParse` would otherwise split it.
+fn quote(token: &str) -> Cow<'_, str> {
+    if token.is_empty() || token.bytes().any(|b| b <= b' ' || b == b',') ||
+       token.contains("//") || token.contains("/*") {
+        Cow::Owned(format!("\"{}\"", token))
+    } else {
+        Cow::Borrowed(token)
+    }
+}
+
+/// Writes one entry per line, in the form `parse` reads.
+impl fmt::Display for Skin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            match *entry {
+                SkinEntry::Surface { ref surface, ref shader } => {
+                    writeln!(f, "{},{}", quote(surface), quote(shader))?
+                }
+                SkinEntry::Tag(ref tag) => writeln!(f, "{},", quote(tag))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The shaders a skin gives the surfaces of a model.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SkinResolution<'s> {
+    /// The shader for each surface, or `None` where the skin has no line
+    /// for it and the game draws its default shader.
+    pub shaders: Vec<Option<&'s str>>,
+    /// Indexes into `Skin::entries` of surface lines that no surface uses.
+    pub unused_entries: Vec<usize>,
+    /// Indexes of the surfaces the skin has no line for.
+    pub uncovered_surfaces: Vec<usize>,
+}
+
+impl SkinResolution<'_> {
+    /// Whether every surface has a shader and every line is used.
+    pub fn is_exact(&self) -> bool {
+        self.unused_entries.is_empty() && self.uncovered_surfaces.is_empty()
+    }
+}
+
+impl Md3 {
+    /// Looks up each surface in `skin` as the renderer does.
+    ///
+    /// Surface names are compared without regard to case, after dropping a
+    /// trailing `_1`-style suffix as `R_LoadMD3` does. The first matching
+    /// line wins.
+    pub fn resolve_shaders<'s>(&self, skin: &'s Skin) -> SkinResolution<'s> {
+        let mut used = vec![false; skin.entries.len()];
+        let mut shaders = Vec::with_capacity(self.surfaces.len());
+        let mut uncovered_surfaces = Vec::new();
+        for (j, surface) in self.surfaces.iter().enumerate() {
+            let name = surface.header.name.to_string_lossy();
+            let bytes = name.as_bytes();
+            let name = if bytes.len() > 2 && bytes[bytes.len() - 2] == b'_' {
+                &name[..name.len() - 2]
+            } else {
+                &name[..]
+            };
+            let found = skin.entries.iter().enumerate().find_map(|(i, entry)| {
+                match *entry {
+                    SkinEntry::Surface { ref surface, ref shader }
+                        if surface.eq_ignore_ascii_case(name) => Some((i, &shader[..])),
+                    _ => None,
+                }
+            });
+            match found {
+                Some((i, shader)) => {
+                    used[i] = true;
+                    shaders.push(Some(shader));
+                }
+                None => {
+                    uncovered_surfaces.push(j);
+                    shaders.push(None);
+                }
+            }
+        }
+        let unused_entries = skin.entries
+            .iter()
+            .enumerate()
+            .filter(|&(i, entry)| !used[i] && matches!(*entry, SkinEntry::Surface { .. }))
+            .map(|(i, _)| i)
+            .collect();
+        SkinResolution {
+            shaders,
+            unused_entries,
+            uncovered_surfaces,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Md3, Md3Builder, SurfaceBuilder, Vec3};
+    use super::{Skin, SkinEntry};
+
+    fn surface(surface: &str, shader: &str) -> SkinEntry {
+        SkinEntry::Surface {
+            surface: surface.to_string(),
+            shader: shader.to_string(),
+        }
+    }
+
+    /// A one-frame model with a surface for each of `names`.
+    fn model(names: &[&str]) -> Md3 {
+        let mut builder = Md3Builder::new("models/skinned.md3").unwrap();
+        builder.add_frame("frame0").unwrap();
+        for name in names {
+            let mut surface = SurfaceBuilder::new(name).unwrap();
+            surface.tex_coords(&[[0.0, 0.0]; 3])
+                .add_triangle([0, 1, 2])
+                .add_frame(&[Vec3::new(0.0, 0.0, 0.0); 3], &[Vec3::new(0.0, 0.0, 1.0); 3]);
+            builder.add_surface(surface);
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn parses_surfaces_and_tags() {
+        let skin = Skin::parse("tag_head,\n\
+                                h_Head,models/players/sarge/head.tga\n\
+                                tag_weapon,\n\
+                                u_torso,\"models/players/sarge/band 2.tga\"\n");
+        assert_eq!(skin.entries,
+                   vec![SkinEntry::Tag("tag_head".to_string()),
+                        surface("h_head", "models/players/sarge/head.tga"),
+                        SkinEntry::Tag("tag_weapon".to_string()),
+                        surface("u_torso", "models/players/sarge/band 2.tga")]);
+    }
+
+    #[test]
+    fn shader_lookup_ignores_case() {
+        let skin = Skin::parse("h_head,first\nH_HEAD,second\n");
+        assert_eq!(skin.shader("H_Head"), Some("first"));
+        assert_eq!(skin.shader("h_head"), Some("first"));
+        assert_eq!(skin.shader("tag_head"), None);
+        assert_eq!(skin.shader("u_torso"), None);
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let skin = Skin {
+            entries: vec![SkinEntry::Tag("tag_torso".to_string()),
+                          surface("l_legs", "models/players/sarge/legs.tga"),
+                          surface("l_belt", "with space, comma"),
+                          surface("l_none", ""),
+                          surface("l_comment", "a//b")],
+        };
+        let text = skin.to_string();
+        assert_eq!(Skin::parse(&text), skin, "{}", text);
+    }
+
+    #[test]
+    fn resolution_reports_unused_and_uncovered() {
+        let model = model(&["body", "Head_1", "legs"]);
+        let skin = Skin::parse("body,textures/body\n\
+                                tag_weapon,\n\
+                                HEAD,textures/head\n\
+                                arms,textures/arms\n");
+        let resolution = model.resolve_shaders(&skin);
+        assert_eq!(resolution.shaders, vec![Some("textures/body"), Some("textures/head"), None]);
+        assert_eq!(resolution.unused_entries, vec![3]);
+        assert_eq!(resolution.uncovered_surfaces, vec![2]);
+        assert!(!resolution.is_exact());
+
+        let skin = Skin::parse("legs,textures/legs\nhead,textures/head\nbody,textures/body\n");
+        let resolution = model.resolve_shaders(&skin);
+        assert_eq!(resolution.shaders,
+                   vec![Some("textures/body"), Some("textures/head"), Some("textures/legs")]);
+        assert!(resolution.is_exact());
+    }
+}
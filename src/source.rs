@@ -0,0 +1,121 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use warning::Warning;
+
+/// Wraps a `Read + Seek` model source and tracks the position relative to
+/// where the model starts, so offsets can be checked and reported without
+/// asking the underlying reader.
+///
+/// A lenient source also collects the warnings raised while reading it.
+pub(crate) struct Source<R> {
+    inner: R,
+    base: u64,
+    pos: u64,
+    len: u64,
+    warnings: Option<Vec<Warning>>,
+}
+
+impl<R: Read + Seek> Source<R> {
+    /// Treats the reader's current position as the start of the model.
+    pub fn new(mut inner: R) -> io::Result<Source<R>> {
+        let base = inner.stream_position()?;
+        let end = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(base))?;
+        Ok(Source {
+            inner,
+            base,
+            pos: 0,
+            len: end.saturating_sub(base),
+            warnings: None,
+        })
+    }
+
+    pub fn lenient(inner: R) -> io::Result<Source<R>> {
+        let mut source = Source::new(inner)?;
+        source.warnings = Some(Vec::new());
+        Ok(source)
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        self.warnings.is_some()
+    }
+
+    pub fn warn(&mut self, warning: Warning) {
+        if let Some(ref mut warnings) = self.warnings {
+            warnings.push(warning);
+        }
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.warnings.take().unwrap_or_default()
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Number of bytes from the start of the model to the end of the reader.
+    pub fn size(&self) -> u64 {
+        self.len
+    }
+
+    pub fn set_position(&mut self, pos: u64) -> io::Result<()> {
+        if pos != self.pos {
+            self.inner.seek(SeekFrom::Start(self.base + pos))?;
+            self.pos = pos;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use {Md3, Md3Error, Section};
+    use test_model::{self, OFS_SURFACES};
+
+    const PREFIX: usize = 7;
+
+    /// `bytes` stored after `PREFIX` bytes of other data, with the cursor at
+    /// the start of the model.
+    fn after_prefix(bytes: &[u8]) -> Cursor<Vec<u8>> {
+        let mut data = vec![0xee; PREFIX];
+        data.extend_from_slice(bytes);
+        let mut cursor = Cursor::new(data);
+        cursor.seek(SeekFrom::Start(PREFIX as u64)).unwrap();
+        cursor
+    }
+
+    #[test]
+    fn reads_from_the_current_position() {
+        let bytes = test_model::bytes();
+        let md3 = Md3::from_reader(after_prefix(&bytes)).unwrap();
+        let expected = Md3::from_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}", md3), format!("{:?}", expected));
+        assert_eq!(md3.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn error_offsets_are_relative_to_the_model() {
+        let mut bytes = test_model::bytes();
+        let surface = test_model::get_i32(&bytes, OFS_SURFACES) as usize;
+        bytes[surface..surface + 4].copy_from_slice(b"IDP2");
+        let err = Md3::from_reader(after_prefix(&bytes)).unwrap_err();
+        match err {
+            Md3Error::BadMagic { offset, section: Section::Surface(0), .. } => {
+                assert_eq!(offset, surface as u64);
+            }
+            ref other => panic!("{:?}", other),
+        }
+        assert_eq!(err.offset(), Md3::from_bytes(&bytes).unwrap_err().offset());
+    }
+}
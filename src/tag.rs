@@ -0,0 +1,240 @@
+use {Mat3, Md3, QPath, Quat, Tag, Vec3};
+
+/// A position and rotation, like the engine's `orientation_t`.
+#[derive(Debug,Copy,Clone,PartialEq,Default)]
+pub struct Orientation {
+    pub origin: Vec3,
+    pub axis: Mat3,
+}
+
+impl Orientation {
+    /// Column-major 4x4 matrix; see `Mat3::to_affine`.
+    pub fn to_affine(&self) -> [f32; 16] {
+        self.axis.to_affine(self.origin)
+    }
+}
+
+impl<'a> From<&'a Tag> for Orientation {
+    fn from(tag: &'a Tag) -> Orientation {
+        Orientation {
+            origin: tag.origin,
+            axis: tag.axis,
+        }
+    }
+}
+
+/// Picks a tag within a frame, either by position or by name.
+pub trait TagSelector {
+    /// Index of the selected tag within `tags`, the tags of one frame.
+    fn select(&self, tags: &[Tag]) -> Option<usize>;
+}
+
+impl<S: TagSelector + ?Sized> TagSelector for &S {
+    fn select(&self, tags: &[Tag]) -> Option<usize> {
+        (**self).select(tags)
+    }
+}
+
+impl TagSelector for usize {
+    fn select(&self, tags: &[Tag]) -> Option<usize> {
+        if *self < tags.len() { Some(*self) } else { None }
+    }
+}
+
+impl TagSelector for str {
+    fn select(&self, tags: &[Tag]) -> Option<usize> {
+        tags.iter().position(|tag| tag.name == *self)
+    }
+}
+
+impl TagSelector for QPath {
+    fn select(&self, tags: &[Tag]) -> Option<usize> {
+        tags.iter().position(|tag| tag.name == *self)
+    }
+}
+
+impl Md3 {
+    /// Number of tags in each frame.
+    pub fn tags_per_frame(&self) -> usize {
+        if self.frames.is_empty() {
+            0
+        } else {
+            self.tags.len() / self.frames.len()
+        }
+    }
+
+    /// The tags of `frame`, in file order.
+    pub fn frame_tags(&self, frame: usize) -> Option<&[Tag]> {
+        let n = self.tags_per_frame();
+        if frame >= self.frames.len() {
+            return None;
+        }
+        self.tags.get(frame * n..(frame + 1) * n)
+    }
+
+    /// The tag of `frame` selected by index or name.
+    pub fn tag<S: TagSelector>(&self, frame: usize, selector: S) -> Option<&Tag> {
+        let tags = self.frame_tags(frame)?;
+        selector.select(tags).map(|i| &tags[i])
+    }
+
+    /// Blends a tag between `frame_a` (at `t = 0`) and `frame_b` (at `t = 1`)
+    /// as `R_LerpTag` does: origin and axes are lerped, then each axis is
+    /// normalized on its own, so the axes may end up slightly skewed.
+    ///
+    /// Returns `None` if either frame or the tag does not exist.
+    pub fn lerp_tag<S: TagSelector>(&self,
+                                    frame_a: usize,
+                                    frame_b: usize,
+                                    t: f32,
+                                    selector: S)
+                                    -> Option<Orientation> {
+        let (a, b) = self.tag_pair(frame_a, frame_b, selector)?;
+        let mut axis = Mat3::IDENTITY;
+        for i in 0..3 {
+            axis[i] = a.axis[i].lerp(b.axis[i], t).normalize();
+        }
+        Some(Orientation {
+            origin: a.origin.lerp(b.origin, t),
+            axis,
+        })
+    }
+
+    /// Like `lerp_tag`, but blends the rotation with a quaternion slerp,
+    /// which keeps the axes orthonormal and turns at a constant rate.
+    pub fn slerp_tag<S: TagSelector>(&self,
+                                     frame_a: usize,
+                                     frame_b: usize,
+                                     t: f32,
+                                     selector: S)
+                                     -> Option<Orientation> {
+        let (a, b) = self.tag_pair(frame_a, frame_b, selector)?;
+        let rotation = Quat::from_mat3(&a.axis).slerp(Quat::from_mat3(&b.axis), t);
+        Some(Orientation {
+            origin: a.origin.lerp(b.origin, t),
+            axis: rotation.to_mat3(),
+        })
+    }
+
+    fn tag_pair<S: TagSelector>(&self,
+                                frame_a: usize,
+                                frame_b: usize,
+                                selector: S)
+                                -> Option<(&Tag, &Tag)> {
+        Some((self.tag(frame_a, &selector)?, self.tag(frame_b, &selector)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use {Mat3, Md3, Md3Builder, QPath, Vec3};
+    use super::Orientation;
+
+    /// Left turned to forward, and forward to right.
+    const TURNED: Mat3 = Mat3 {
+        rows: [Vec3 {
+                   x: 0.0,
+                   y: 1.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: -1.0,
+                   y: 0.0,
+                   z: 0.0,
+               },
+               Vec3 {
+                   x: 0.0,
+                   y: 0.0,
+                   z: 1.0,
+               }],
+    };
+
+    /// Two frames with `tag_a` turning a quarter turn about z while rising,
+    /// and `tag_b` still.
+    fn model() -> Md3 {
+        let mut builder = Md3Builder::new("models/tags.md3").unwrap();
+        for (i, &axis) in [Mat3::IDENTITY, TURNED].iter().enumerate() {
+            let frame = builder.add_frame(&format!("frame{}", i)).unwrap();
+            builder.add_tag(frame, "tag_a", Vec3::new(0.0, 0.0, 10.0 * i as f32), axis)
+                .unwrap()
+                .add_tag(frame, "tag_b", Vec3::new(1.0, 2.0, 3.0), Mat3::IDENTITY)
+                .unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    fn assert_axis(a: Mat3, b: Mat3) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).length() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn selectors() {
+        let md3 = model();
+        assert_eq!(md3.tags_per_frame(), 2);
+        assert_eq!(md3.frame_tags(1).unwrap().len(), 2);
+        let by_index = md3.tag(1, 1).unwrap();
+        let by_name = md3.tag(1, "tag_b").unwrap();
+        let by_path = md3.tag(1, QPath::new("tag_b").unwrap()).unwrap();
+        assert_eq!(by_index.name, "tag_b");
+        assert!(ptr::eq(by_index, by_name));
+        assert!(ptr::eq(by_index, by_path));
+        assert_eq!(md3.tag(1, "tag_a").unwrap().axis, TURNED);
+    }
+
+    #[test]
+    fn missing_frames_and_tags() {
+        let md3 = model();
+        assert!(md3.frame_tags(2).is_none());
+        assert!(md3.tag(2, 0).is_none());
+        assert!(md3.tag(0, 2).is_none());
+        assert!(md3.tag(0, "tag_c").is_none());
+        assert!(md3.lerp_tag(0, 2, 0.5, "tag_a").is_none());
+        assert!(md3.lerp_tag(0, 1, 0.5, "tag_c").is_none());
+        assert!(md3.slerp_tag(2, 0, 0.5, 0).is_none());
+    }
+
+    #[test]
+    fn endpoints_are_the_frame_tags() {
+        let md3 = model();
+        for &(t, frame) in &[(0.0, 0), (1.0, 1)] {
+            let expected = Orientation::from(md3.tag(frame, "tag_a").unwrap());
+            assert_eq!(md3.lerp_tag(0, 1, t, "tag_a"), Some(expected));
+            let slerped = md3.slerp_tag(0, 1, t, "tag_a").unwrap();
+            assert_eq!(slerped.origin, expected.origin);
+            assert_axis(slerped.axis, expected.axis);
+        }
+    }
+
+    #[test]
+    fn midpoint() {
+        let md3 = model();
+        let half = 0.5f32.sqrt();
+        // Both turn forward halfway to left.
+        let turned = Mat3::from_rows([Vec3::new(half, half, 0.0),
+                                      Vec3::new(-half, half, 0.0),
+                                      Vec3::new(0.0, 0.0, 1.0)]);
+
+        let lerped = md3.lerp_tag(0, 1, 0.5, "tag_a").unwrap();
+        assert_eq!(lerped.origin, Vec3::new(0.0, 0.0, 5.0));
+        assert_axis(lerped.axis, turned);
+
+        let slerped = md3.slerp_tag(0, 1, 0.5, "tag_a").unwrap();
+        assert_eq!(slerped.origin, Vec3::new(0.0, 0.0, 5.0));
+        assert_axis(slerped.axis, turned);
+
+        // A quarter of the way, slerp has turned 22.5 degrees, while lerp
+        // has turned less.
+        let (sin, cos) = 22.5f32.to_radians().sin_cos();
+        let slerped = md3.slerp_tag(0, 1, 0.25, "tag_a").unwrap();
+        assert_axis(slerped.axis,
+                    Mat3::from_rows([Vec3::new(cos, sin, 0.0),
+                                     Vec3::new(-sin, cos, 0.0),
+                                     Vec3::new(0.0, 0.0, 1.0)]));
+        let lerped = md3.lerp_tag(0, 1, 0.25, "tag_a").unwrap();
+        assert!(lerped.axis[0].y < sin);
+    }
+}
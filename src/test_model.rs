@@ -0,0 +1,96 @@
+//! Small models shared by the unit tests.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use {Mat3, Md3, Md3Builder, SurfaceBuilder, Vec3};
+use {HEADER_SIZE, FRAME_SIZE, TAG_SIZE};
+
+/// Offsets of header fields.
+pub const NUM_FRAMES: usize = 76;
+pub const OFS_FRAMES: usize = 92;
+pub const OFS_TAGS: usize = 96;
+pub const OFS_SURFACES: usize = 100;
+pub const OFS_EOF: usize = 104;
+
+/// Offset of the first frame's `max_bounds` and name.
+pub const FRAME_MAX_BOUNDS: usize = HEADER_SIZE as usize + 12;
+pub const FRAME_NAME: usize = HEADER_SIZE as usize + 40;
+
+/// Offset of `num_frames` within a surface header.
+pub const SURFACE_NUM_FRAMES: usize = 72;
+
+/// A two-frame model with one tag and one triangle.
+pub fn model() -> Md3 {
+    let positions = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(8.0, 0.0, 0.0), Vec3::new(0.0, 8.0, 4.0)];
+    let normals = [Vec3::new(0.0, 0.0, 1.0); 3];
+    let mut surface = SurfaceBuilder::new("body").unwrap();
+    surface.add_shader("textures/body").unwrap();
+    surface.tex_coords(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]])
+        .add_triangle([0, 1, 2])
+        .add_frame(&positions, &normals)
+        .add_frame(&positions.iter().map(|p| *p * 2.0).collect::<Vec<_>>(), &normals);
+
+    let mut builder = Md3Builder::new("models/test.md3").unwrap();
+    for i in 0..2 {
+        let frame = builder.add_frame(&format!("frame{}", i)).unwrap();
+        builder.add_tag(frame, "tag_top", Vec3::new(0.0, 0.0, 10.0 + i as f32), Mat3::IDENTITY)
+            .unwrap();
+    }
+    builder.add_surface(surface);
+    builder.build().unwrap()
+}
+
+pub fn bytes() -> Vec<u8> {
+    model().to_bytes().unwrap()
+}
+
+pub fn get_i32(bytes: &[u8], at: usize) -> i32 {
+    let mut raw = [0; 4];
+    raw.copy_from_slice(&bytes[at..at + 4]);
+    i32::from_le_bytes(raw)
+}
+
+pub fn set_i32(bytes: &mut [u8], at: usize, value: i32) {
+    bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// `bytes()` with the tags stored before the frames.
+pub fn swapped_bytes() -> Vec<u8> {
+    let mut bytes = bytes();
+    let frames = HEADER_SIZE as usize..HEADER_SIZE as usize + 2 * FRAME_SIZE as usize;
+    let tags = frames.end..frames.end + 2 * TAG_SIZE as usize;
+    let mut swapped = bytes[tags.clone()].to_vec();
+    swapped.extend_from_slice(&bytes[frames.clone()]);
+    bytes[frames.start..tags.end].copy_from_slice(&swapped);
+    set_i32(&mut bytes, OFS_TAGS, frames.start as i32);
+    set_i32(&mut bytes, OFS_FRAMES, (frames.start + tags.len()) as i32);
+    bytes
+}
+
+/// `bytes()` with four bytes of padding between the frames and the tags and
+/// eight bytes after the end of the file.
+pub fn padded_bytes() -> Vec<u8> {
+    let mut bytes = bytes();
+    let tags = get_i32(&bytes, OFS_TAGS) as usize;
+    bytes.splice(tags..tags, vec![0xab; 4]);
+    for &field in &[OFS_TAGS, OFS_SURFACES, OFS_EOF] {
+        let value = get_i32(&bytes, field);
+        set_i32(&mut bytes, field, value + 4);
+    }
+    bytes.extend_from_slice(&[0xcd; 8]);
+    bytes
+}
+
+/// An empty directory under the system temp directory, unique to this
+/// process and `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("md3_rs_{}_{}", name, process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,139 @@
+pub(crate) struct Token<'a> {
+    pub text: &'a str,
+    /// Byte offset of `text` in the input.
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Splits text into tokens the way `COM_Parse` does: whitespace separated,
+/// with `//` and `/* */` comments and double-quoted strings. Lines and
+/// columns count from 1.
+pub(crate) struct Tokenizer<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+    line_start: usize,
+    /// Whether a comma also ends a token, as in the renderer's `CommaParse`.
+    commas: bool,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(text: &'a str) -> Tokenizer<'a> {
+        Tokenizer {
+            text,
+            pos: 0,
+            line: 1,
+            line_start: 0,
+            commas: false,
+        }
+    }
+
+    pub fn with_commas(text: &'a str) -> Tokenizer<'a> {
+        Tokenizer { commas: true, ..Tokenizer::new(text) }
+    }
+
+    /// Consumes `byte` if it comes next, without skipping anything first.
+    pub fn eat(&mut self, byte: u8) -> bool {
+        if self.text.as_bytes().get(self.pos) == Some(&byte) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.text.as_bytes()[self.pos] == b'\n' {
+            self.line += 1;
+            self.line_start = self.pos + 1;
+        }
+        self.pos += 1;
+    }
+
+    fn skip_space(&mut self) {
+        let bytes = self.text.as_bytes();
+        while self.pos < bytes.len() {
+            if bytes[self.pos] <= b' ' {
+                self.advance();
+            } else if bytes[self.pos..].starts_with(b"//") {
+                while self.pos < bytes.len() && bytes[self.pos] != b'\n' {
+                    self.advance();
+                }
+            } else if bytes[self.pos..].starts_with(b"/*") {
+                while self.pos < bytes.len() && !bytes[self.pos..].starts_with(b"*/") {
+                    self.advance();
+                }
+                self.pos = (self.pos + 2).min(bytes.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Where the next token starts, or the end of the text.
+    pub fn location(&self) -> (usize, usize) {
+        (self.line, self.pos - self.line_start + 1)
+    }
+
+    /// Byte offset of the next token, or the end of the text.
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    pub fn peek(&mut self) -> Option<Token<'a>> {
+        let saved = (self.pos, self.line, self.line_start);
+        let token = self.next();
+        self.pos = saved.0;
+        self.line = saved.1;
+        self.line_start = saved.2;
+        token
+    }
+
+    /// The next token if it is on the current line, as `COM_ParseExt` with
+    /// `allowLineBreaks` off.
+    pub fn next_on_line(&mut self) -> Option<Token<'a>> {
+        let saved = (self.pos, self.line, self.line_start);
+        match self.next() {
+            Some(token) if token.line == saved.1 => Some(token),
+            _ => {
+                self.pos = saved.0;
+                self.line = saved.1;
+                self.line_start = saved.2;
+                None
+            }
+        }
+    }
+
+    pub fn next(&mut self) -> Option<Token<'a>> {
+        self.skip_space();
+        let bytes = self.text.as_bytes();
+        if self.pos >= bytes.len() {
+            return None;
+        }
+        let (line, column) = self.location();
+        let (offset, text) = if bytes[self.pos] == b'"' {
+            self.advance();
+            let start = self.pos;
+            while self.pos < bytes.len() && bytes[self.pos] != b'"' {
+                self.advance();
+            }
+            let text = &self.text[start..self.pos];
+            self.pos = (self.pos + 1).min(bytes.len());
+            (start, text)
+        } else {
+            let start = self.pos;
+            while self.pos < bytes.len() && bytes[self.pos] > b' ' &&
+                  !(self.commas && bytes[self.pos] == b',') {
+                self.advance();
+            }
+            (start, &self.text[start..self.pos])
+        };
+        Some(Token {
+            text,
+            offset,
+            line,
+            column,
+        })
+    }
+}
@@ -0,0 +1,429 @@
+use std::io::{Read, Seek};
+
+use {Md3, Md3Error, Md3Header, Section, SurfaceHeader, Result};
+use {MD3_MAGIC, HEADER_SIZE, FRAME_SIZE, TAG_SIZE, SURFACE_HEADER_SIZE, SHADER_SIZE,
+     TRIANGLE_SIZE, ST_SIZE, XYZNORMAL_SIZE};
+use source::Source;
+use warning::{Warning, WarningKind};
+
+/// Outcome of a structural validation pass.
+///
+/// An empty `issues` list means every offset and count in the file is
+/// consistent and the model can be loaded.
+#[derive(Debug)]
+pub struct Report {
+    pub issues: Vec<Md3Error>,
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Converts the report into the first issue found, if any.
+    pub fn into_result(self) -> Result<()> {
+        match self.issues.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Header and surface headers of a file, as found by `scan`.
+#[derive(Clone)]
+pub(crate) struct Layout {
+    pub header: Md3Header,
+    pub surfaces: Vec<SurfaceLayout>,
+    /// Byte ranges not covered by any structure, in file order.
+    pub gaps: Vec<(u64, u64)>,
+}
+
+#[derive(Clone)]
+pub(crate) struct SurfaceLayout {
+    pub offset: u64,
+    pub header: SurfaceHeader,
+}
+
+struct Region {
+    start: u64,
+    end: u64,
+    section: Section,
+}
+
+struct Scanner {
+    issues: Vec<Md3Error>,
+    /// Regions in the standard order.
+    regions: Vec<Region>,
+}
+
+impl Scanner {
+    fn count(&mut self, value: i32, offset: u64, section: Section) -> u64 {
+        if value < 0 {
+            self.issues.push(Md3Error::CountOutOfRange {
+                offset,
+                section,
+                count: value,
+            });
+            0
+        } else {
+            value as u64
+        }
+    }
+
+    /// Records the region `[start, start + size)` and checks that it lies
+    /// within `[lower, upper]`.
+    fn region(&mut self,
+              offset: u64,
+              section: Section,
+              start: i64,
+              size: Option<u64>,
+              lower: u64,
+              upper: u64) {
+        let end = size.and_then(|size| (start.max(0) as u64).checked_add(size));
+        match end {
+            Some(end) if start >= lower as i64 && end <= upper => {
+                if end > start as u64 {
+                    self.regions.push(Region {
+                        start: start as u64,
+                        end,
+                        section,
+                    });
+                }
+            }
+            _ => {
+                self.issues.push(Md3Error::RegionOutOfBounds {
+                    offset,
+                    section,
+                    start,
+                    end: end.unwrap_or(u64::MAX),
+                    lower,
+                    upper,
+                })
+            }
+        }
+    }
+
+    /// Rejects each region that starts before the region the standard
+    /// layout puts ahead of it. A lenient load only warns, since every
+    /// structure is found through its offset.
+    fn check_order<R: Read + Seek>(&mut self, buff: &mut Source<R>) {
+        for pair in self.regions.windows(2) {
+            if pair[1].start >= pair[0].start {
+                continue;
+            }
+            if buff.is_lenient() {
+                buff.warn(Warning {
+                    kind: WarningKind::OutOfOrder { after: pair[0].section },
+                    offset: pair[1].start,
+                    section: pair[1].section,
+                    assumed: "kept the stored order".to_string(),
+                });
+            } else {
+                self.issues.push(Md3Error::OutOfOrder {
+                    offset: pair[1].start,
+                    section: pair[1].section,
+                    after: pair[0].section,
+                });
+            }
+        }
+    }
+
+    fn check_overlaps(&mut self) {
+        self.regions.sort_by_key(|r| r.start);
+        let mut last: Option<&Region> = None;
+        for region in &self.regions {
+            match last {
+                Some(prev) if region.start < prev.end => {
+                    self.issues.push(Md3Error::Overlap {
+                        offset: region.start,
+                        section: region.section,
+                        other: prev.section,
+                    });
+                    if region.end > prev.end {
+                        last = Some(region);
+                    }
+                }
+                _ => last = Some(region),
+            }
+        }
+    }
+}
+
+fn size(count: u64, elem: u64) -> Option<u64> {
+    count.checked_mul(elem)
+}
+
+/// Walks the header and every surface header, checking every offset and
+/// count against the buffer and against each other.
+///
+/// Fails outright only when the header itself cannot be read.
+pub(crate) fn scan<R: Read + Seek>(buff: &mut Source<R>) -> Result<(Vec<Md3Error>, Layout)> {
+    let len = buff.size();
+    let mut scanner = Scanner {
+        issues: Vec::new(),
+        regions: Vec::new(),
+    };
+
+    let header = Md3::read_md3_header(buff)?;
+    let section = Section::Header;
+
+    let num_frames = scanner.count(header.num_frames, 0, section);
+    let num_tags = scanner.count(header.num_tags, 0, section);
+    let num_surfaces = scanner.count(header.num_surfaces, 0, section);
+
+    let lenient = buff.is_lenient();
+    let eof_valid = header.ofs_eof >= HEADER_SIZE as i32 && header.ofs_eof as u64 <= len;
+    let eof = if lenient {
+        len
+    } else if !eof_valid {
+        scanner.issues.push(Md3Error::OffsetOutOfBounds {
+            offset: 0,
+            section,
+            target: header.ofs_eof as i64,
+            len,
+        });
+        len
+    } else {
+        header.ofs_eof as u64
+    };
+
+    scanner.regions.push(Region {
+        start: 0,
+        end: HEADER_SIZE,
+        section,
+    });
+    scanner.region(0,
+                   Section::Frame(0),
+                   header.ofs_frames as i64,
+                   size(num_frames, FRAME_SIZE),
+                   0,
+                   eof);
+    scanner.region(0,
+                   Section::Tag(0),
+                   header.ofs_tags as i64,
+                   num_frames.checked_mul(num_tags).and_then(|n| size(n, TAG_SIZE)),
+                   0,
+                   eof);
+
+    let mut surfaces = Vec::new();
+    let mut pos = header.ofs_surfaces as i64;
+    for j in 0..num_surfaces {
+        let j = j as usize;
+        let section = Section::Surface(j);
+
+        if pos < 0 || pos as u64 + SURFACE_HEADER_SIZE > eof {
+            scanner.issues.push(Md3Error::RegionOutOfBounds {
+                offset: 0,
+                section,
+                start: pos,
+                end: (pos.max(0) as u64).saturating_add(SURFACE_HEADER_SIZE),
+                lower: HEADER_SIZE,
+                upper: eof,
+            });
+            break;
+        }
+        let start = pos as u64;
+        buff.set_position(start)?;
+        let surface_header = match Md3::read_surface_header(buff, j) {
+            Ok(surface_header) => surface_header,
+            Err(err) => {
+                scanner.issues.push(err);
+                break;
+            }
+        };
+
+        if surface_header.ident != MD3_MAGIC {
+            scanner.issues.push(Md3Error::BadMagic {
+                offset: start,
+                section,
+                found: surface_header.ident,
+            });
+        }
+        if surface_header.num_frames != header.num_frames && !lenient {
+            scanner.issues.push(Md3Error::FrameCountMismatch {
+                offset: start,
+                section,
+                expected: header.num_frames,
+                found: surface_header.num_frames,
+            });
+        }
+
+        let mut surface_frames = scanner.count(surface_header.num_frames, start, section);
+        if lenient {
+            // Only the frames the model header declares are read.
+            surface_frames = surface_frames.min(num_frames);
+        }
+        let num_shaders = scanner.count(surface_header.num_shaders, start, section);
+        let num_verts = scanner.count(surface_header.num_verts, start, section);
+        let num_triangles = scanner.count(surface_header.num_triangles, start, section);
+
+        let end = start as i64 + surface_header.ofs_end as i64;
+        if end < (start + SURFACE_HEADER_SIZE) as i64 || end as u64 > eof {
+            scanner.issues.push(Md3Error::RegionOutOfBounds {
+                offset: start,
+                section,
+                start: start as i64,
+                end: end.max(0) as u64,
+                lower: start + SURFACE_HEADER_SIZE,
+                upper: eof,
+            });
+            break;
+        }
+        let end = end as u64;
+
+        scanner.regions.push(Region {
+            start,
+            end: start + SURFACE_HEADER_SIZE,
+            section,
+        });
+        scanner.region(start,
+                       Section::Shader { surface: j, index: 0 },
+                       start as i64 + surface_header.ofs_shaders as i64,
+                       size(num_shaders, SHADER_SIZE),
+                       start,
+                       end);
+        scanner.region(start,
+                       Section::Triangle { surface: j, index: 0 },
+                       start as i64 + surface_header.ofs_triangles as i64,
+                       size(num_triangles, TRIANGLE_SIZE),
+                       start,
+                       end);
+        scanner.region(start,
+                       Section::TexCoord { surface: j, index: 0 },
+                       start as i64 + surface_header.ofs_st as i64,
+                       size(num_verts, ST_SIZE),
+                       start,
+                       end);
+        scanner.region(start,
+                       Section::Vertex { surface: j, frame: 0, index: 0 },
+                       start as i64 + surface_header.ofs_xyznormal as i64,
+                       surface_frames.checked_mul(num_verts).and_then(|n| size(n, XYZNORMAL_SIZE)),
+                       start,
+                       end);
+
+        surfaces.push(SurfaceLayout {
+            offset: start,
+            header: surface_header,
+        });
+        pos = end as i64;
+    }
+
+    scanner.check_order(buff);
+    scanner.check_overlaps();
+
+    let mut header = header;
+    let data_end = scanner.regions.iter().map(|r| r.end).max().unwrap_or(HEADER_SIZE);
+    if lenient && (!eof_valid || data_end > header.ofs_eof as u64) {
+        buff.warn(Warning {
+            kind: WarningKind::EofMismatch {
+                stored: header.ofs_eof,
+                actual: len,
+            },
+            offset: 0,
+            section,
+            assumed: format!("file ends at offset {}", len),
+        });
+        header.ofs_eof = len as i32;
+    }
+
+    let mut gaps = Vec::new();
+    let mut covered = 0;
+    for region in &scanner.regions {
+        if region.start > covered {
+            gaps.push((covered, region.start));
+        }
+        covered = covered.max(region.end);
+    }
+    if len > covered {
+        gaps.push((covered, len));
+    }
+
+    Ok((scanner.issues,
+        Layout {
+            header,
+            surfaces,
+            gaps,
+        }))
+}
+
+pub(crate) fn validate<R: Read + Seek>(buff: &mut Source<R>) -> Report {
+    match scan(buff) {
+        Ok((issues, _)) => Report { issues },
+        Err(err) => Report { issues: vec![err] },
+    }
+}
+
+/// Runs `scan` and fails on the first issue found.
+pub(crate) fn check<R: Read + Seek>(buff: &mut Source<R>) -> Result<Layout> {
+    let (issues, layout) = scan(buff)?;
+    match issues.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(layout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Md3, Md3Error, Section};
+    use test_model::{self, OFS_FRAMES, OFS_TAGS};
+    use warning::WarningKind;
+
+    #[test]
+    fn builder_output_is_clean() {
+        let report = Md3::validate(&test_model::bytes());
+        assert!(report.is_valid(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn out_of_order_is_an_issue() {
+        let bytes = test_model::swapped_bytes();
+        let tags = test_model::get_i32(&bytes, OFS_TAGS) as u64;
+        let report = Md3::validate(&bytes);
+        match report.issues[..] {
+            [Md3Error::OutOfOrder { offset, section: Section::Tag(0), after: Section::Frame(0) }]
+                if offset == tags => {}
+            ref issues => panic!("{:?}", issues),
+        }
+        assert!(Md3::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn out_of_order_is_a_lenient_warning() {
+        let bytes = test_model::swapped_bytes();
+        let (swapped, warnings) = Md3::from_bytes_lenient(&bytes).unwrap();
+        assert_eq!(warnings.len(), 1);
+        let warning = &warnings[0];
+        assert_eq!(warning.kind, WarningKind::OutOfOrder { after: Section::Frame(0) });
+        assert_eq!(warning.section, Section::Tag(0));
+        assert_eq!(warning.offset, test_model::get_i32(&bytes, OFS_TAGS) as u64);
+
+        let model = test_model::model();
+        assert_eq!(swapped.tags[1].origin, model.tags[1].origin);
+        assert_eq!(swapped.frames[1].name, model.frames[1].name);
+    }
+
+    #[test]
+    fn overlap_is_an_issue() {
+        let mut bytes = test_model::bytes();
+        let frames = test_model::get_i32(&bytes, OFS_FRAMES);
+        test_model::set_i32(&mut bytes, OFS_TAGS, frames);
+        let report = Md3::validate(&bytes);
+        match report.issues[..] {
+            [Md3Error::Overlap { section: Section::Tag(0), other: Section::Frame(0), .. }] => {}
+            ref issues => panic!("{:?}", issues),
+        }
+        assert!(Md3::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn reports_every_issue() {
+        let mut bytes = test_model::bytes();
+        test_model::set_i32(&mut bytes, OFS_FRAMES, -8);
+        test_model::set_i32(&mut bytes, OFS_TAGS, 1 << 20);
+        let report = Md3::validate(&bytes);
+        assert_eq!(report.issues.len(), 2, "{:?}", report.issues);
+        let sections: Vec<_> = report.issues.iter().map(|e| e.section()).collect();
+        assert_eq!(sections, [Some(Section::Frame(0)), Some(Section::Tag(0))]);
+        assert!(report.into_result().is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+use {Surface, Vec3, Vertex};
+
+/// Size of one unit of a stored vertex coordinate.
+pub const MD3_XYZ_SCALE: f32 = 1.0 / 64.0;
+
+/// A position quantized to MD3's fixed-point coordinates.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct Quantized {
+    pub xyz: [i16; 3],
+    /// Whether a coordinate lay outside the storable range of about ±512
+    /// units and was clamped to it.
+    pub clamped: bool,
+}
+
+/// Quantizes `p` to 1/64 units, clamping coordinates that do not fit.
+pub fn quantize_position(p: Vec3) -> Quantized {
+    let mut xyz = [0; 3];
+    let mut clamped = false;
+    for (q, v) in xyz.iter_mut().zip(&[p.x, p.y, p.z]) {
+        let scaled = (v / MD3_XYZ_SCALE).round();
+        if scaled.is_nan() || scaled < i16::MIN as f32 || scaled > i16::MAX as f32 {
+            clamped = true;
+        }
+        *q = scaled as i16;
+    }
+    Quantized { xyz, clamped }
+}
+
+const FUNCTABLE_SIZE: usize = 1024;
+const FUNCTABLE_MASK: usize = FUNCTABLE_SIZE - 1;
+
+/// The renderer's `tr.sinTable`, built with the same mix of float and
+/// double arithmetic so that every entry matches bit for bit.
+fn sin_table() -> &'static [f32; FUNCTABLE_SIZE] {
+    static TABLE: OnceLock<[f32; FUNCTABLE_SIZE]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.0; FUNCTABLE_SIZE];
+        for (i, v) in table.iter_mut().enumerate() {
+            let degrees = i as f32 * 360.0 / (FUNCTABLE_SIZE - 1) as f32;
+            *v = (degrees as f64 * PI / 180.0).sin() as f32;
+        }
+        table
+    })
+}
+
+/// Unpacks a normal the way the renderer does, through its sine table.
+pub fn decode_normal(normal: i16) -> Vec3 {
+    let table = sin_table();
+    let lat = ((normal as u16 >> 8) & 0xff) as usize * (FUNCTABLE_SIZE / 256);
+    let lng = (normal as u16 & 0xff) as usize * (FUNCTABLE_SIZE / 256);
+    Vec3 {
+        x: table[(lat + FUNCTABLE_SIZE / 4) & FUNCTABLE_MASK] * table[lng],
+        y: table[lat] * table[lng],
+        z: table[(lng + FUNCTABLE_SIZE / 4) & FUNCTABLE_MASK],
+    }
+}
+
+/// Unpacks a normal with `sin` and `cos`, taking each byte as a fraction
+/// of a full turn in steps of 360/255 degrees as the file format describes.
+pub fn decode_normal_analytic(normal: i16) -> Vec3 {
+    let step = 2.0 * PI / 255.0;
+    let lat = ((normal as u16 >> 8) & 0xff) as f64 * step;
+    let lng = (normal as u16 & 0xff) as f64 * step;
+    Vec3 {
+        x: (lat.cos() * lng.sin()) as f32,
+        y: (lat.sin() * lng.sin()) as f32,
+        z: lng.cos() as f32,
+    }
+}
+
+/// Packs a direction into the latitude/longitude byte pair, as q3map does
+/// but rounding to the nearest step instead of truncating.
+///
+/// `n` need not be unit length. The zero vector encodes as straight down.
+pub fn encode_normal(n: Vec3) -> i16 {
+    let Vec3 { x, y, z } = n.normalize();
+    let (lat, lng) = if x == 0.0 && y == 0.0 {
+        if z > 0.0 { (0, 0) } else { (0, 128) }
+    } else {
+        let lat = y.atan2(x).to_degrees() * (255.0 / 360.0);
+        let lng = z.clamp(-1.0, 1.0).acos().to_degrees() * (255.0 / 360.0);
+        (lat.round() as i32 & 0xff, lng.round() as i32 & 0xff)
+    };
+    ((lat << 8) | lng) as u16 as i16
+}
+
+impl Vertex {
+    pub fn position(&self) -> Vec3 {
+        Vec3 {
+            x: self.x as f32 * MD3_XYZ_SCALE,
+            y: self.y as f32 * MD3_XYZ_SCALE,
+            z: self.z as f32 * MD3_XYZ_SCALE,
+        }
+    }
+
+    /// The normal as the renderer decodes it; see `decode_normal`.
+    pub fn normal_vec(&self) -> Vec3 {
+        decode_normal(self.normal)
+    }
+
+    /// The normal decoded with `sin` and `cos`; see `decode_normal_analytic`.
+    pub fn normal_vec_analytic(&self) -> Vec3 {
+        decode_normal_analytic(self.normal)
+    }
+}
+
+impl Surface {
+    /// Decoded positions of `frame`; empty if the frame does not exist.
+    pub fn positions(&self, frame: usize) -> impl Iterator<Item = Vec3> + '_ {
+        self.vertices.get(frame).into_iter().flatten().map(Vertex::position)
+    }
+
+    /// Decoded normals of `frame`; empty if the frame does not exist.
+    pub fn normals(&self, frame: usize) -> impl Iterator<Item = Vec3> + '_ {
+        self.vertices.get(frame).into_iter().flatten().map(Vertex::normal_vec)
+    }
+
+    /// Decoded positions of `frame` in one contiguous buffer.
+    pub fn decode_positions(&self, frame: usize) -> Option<Vec<Vec3>> {
+        self.vertices.get(frame).map(|vertices| vertices.iter().map(Vertex::position).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_normal, decode_normal_analytic, encode_normal, quantize_position,
+                MD3_XYZ_SCALE, Quantized};
+    use Vec3;
+    use test_model;
+
+    // A step of either byte is about 1.4 degrees, so rounding to the nearest
+    // code leaves an error of about a degree.
+    const TOLERANCE: f32 = 0.999;
+
+    // The engine's table spreads 360 degrees over 1023 entries and takes four
+    // entries per step, so its angles run up to a step short of the analytic
+    // ones and do not quite form a unit vector.
+    const TABLE_TOLERANCE: f32 = 0.994;
+
+    #[test]
+    fn decoded_normals_are_unit_length() {
+        for normal in i16::MIN..=i16::MAX {
+            assert!((decode_normal_analytic(normal).length() - 1.0).abs() < 1e-5, "{}", normal);
+            assert!((decode_normal(normal).length() - 1.0).abs() < 0.005, "{}", normal);
+        }
+    }
+
+    #[test]
+    fn table_matches_analytic_decoding() {
+        for normal in i16::MIN..=i16::MAX {
+            let a = decode_normal(normal);
+            let b = decode_normal_analytic(normal);
+            assert!(a.dot(b) > TABLE_TOLERANCE, "{}", normal);
+        }
+    }
+
+    #[test]
+    fn round_trip_analytic() {
+        for normal in i16::MIN..=i16::MAX {
+            let n = decode_normal_analytic(normal);
+            let back = decode_normal_analytic(encode_normal(n));
+            assert!(n.dot(back) > TOLERANCE, "{}", normal);
+        }
+    }
+
+    #[test]
+    fn round_trip_table() {
+        for normal in i16::MIN..=i16::MAX {
+            let n = decode_normal(normal);
+            let back = decode_normal(encode_normal(n));
+            assert!(n.dot(back) > TABLE_TOLERANCE, "{}", normal);
+        }
+    }
+
+    #[test]
+    fn poles() {
+        let up = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+        let down = Vec3 { x: 0.0, y: 0.0, z: -1.0 };
+        assert_eq!(encode_normal(up), 0);
+        assert_eq!(encode_normal(down), 128);
+        assert!(decode_normal(encode_normal(up)).dot(up) > TOLERANCE);
+        assert!(decode_normal(encode_normal(down)).dot(down) > TOLERANCE);
+    }
+
+    #[test]
+    fn positions_round_trip_at_xyz_scale() {
+        let p = Vec3::new(1.5, -2.015625, 100.0 / 3.0);
+        let Quantized { xyz, clamped } = quantize_position(p);
+        assert!(!clamped);
+        assert_eq!(xyz, [96, -129, 2133]);
+        let back = Vec3::new(xyz[0] as f32, xyz[1] as f32, xyz[2] as f32) * MD3_XYZ_SCALE;
+        assert_eq!(back.x, 1.5);
+        assert_eq!(back.y, -2.015625);
+        assert!((back.z - p.z).abs() <= MD3_XYZ_SCALE / 2.0);
+    }
+
+    #[test]
+    fn positions_clamp_at_512() {
+        let edge = quantize_position(Vec3::new(i16::MAX as f32 * MD3_XYZ_SCALE, -512.0, 0.0));
+        assert_eq!(edge, Quantized { xyz: [i16::MAX, i16::MIN, 0], clamped: false });
+        let past = quantize_position(Vec3::new(512.0, -600.0, 0.0));
+        assert_eq!(past, Quantized { xyz: [i16::MAX, i16::MIN, 0], clamped: true });
+        assert!(quantize_position(Vec3::new(0.0, f32::NAN, 0.0)).clamped);
+    }
+
+    #[test]
+    fn decode_positions_matches_vertices() {
+        let md3 = test_model::model();
+        let surface = &md3.surfaces[0];
+        for frame in 0..2 {
+            let expected: Vec<_> = surface.vertices[frame].iter().map(|v| v.position()).collect();
+            assert_eq!(surface.decode_positions(frame), Some(expected.clone()));
+            assert_eq!(surface.positions(frame).collect::<Vec<_>>(), expected);
+        }
+        assert_eq!(surface.decode_positions(1).unwrap()[1], Vec3::new(16.0, 0.0, 0.0));
+        assert_eq!(surface.decode_positions(2), None);
+    }
+}
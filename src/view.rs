@@ -0,0 +1,313 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::Cursor;
+
+use {Mat3, Md3, Md3Header, Result, SurfaceHeader, TexCoord, Triangle, Vec3, Vertex};
+use {FRAME_SIZE, TAG_SIZE, SHADER_SIZE, TRIANGLE_SIZE, ST_SIZE, XYZNORMAL_SIZE, MAX_QPATH};
+use source::Source;
+use validate::{self, Layout};
+
+fn s32_at(bytes: &[u8], at: usize) -> i32 {
+    LittleEndian::read_i32(&bytes[at..])
+}
+
+fn s16_at(bytes: &[u8], at: usize) -> i16 {
+    LittleEndian::read_i16(&bytes[at..])
+}
+
+fn f32_at(bytes: &[u8], at: usize) -> f32 {
+    LittleEndian::read_f32(&bytes[at..])
+}
+
+fn vec3_at(bytes: &[u8], at: usize) -> Vec3 {
+    Vec3 {
+        x: f32_at(bytes, at),
+        y: f32_at(bytes, at + 4),
+        z: f32_at(bytes, at + 8),
+    }
+}
+
+fn name_at(bytes: &[u8], at: usize, len: usize) -> &[u8] {
+    let raw = &bytes[at..at + len];
+    let end = raw.iter().position(|x| *x == b'\0').unwrap_or(len);
+    &raw[..end]
+}
+
+/// A validated MD3 file that decodes its contents on demand.
+///
+/// Construction runs the same validation as `Md3::from_bytes`, after which
+/// every accessor reads straight from the borrowed bytes without allocating.
+pub struct Md3View<'a> {
+    bytes: &'a [u8],
+    layout: Layout,
+}
+
+impl<'a> Md3View<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Md3View<'a>> {
+        let mut buff = Source::new(Cursor::new(bytes))?;
+        let layout = validate::check(&mut buff)?;
+        Ok(Md3View { bytes, layout })
+    }
+
+    pub fn header(&self) -> &Md3Header {
+        &self.layout.header
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.layout.header.num_frames as usize
+    }
+
+    pub fn num_tags(&self) -> usize {
+        self.layout.header.num_tags as usize
+    }
+
+    pub fn frames(&self) -> impl ExactSizeIterator<Item = FrameView<'a>> + 'a {
+        let bytes = self.bytes;
+        let start = self.layout.header.ofs_frames as usize;
+        (0..self.num_frames()).map(move |i| {
+            let at = start + i * FRAME_SIZE as usize;
+            FrameView { bytes: &bytes[at..at + FRAME_SIZE as usize] }
+        })
+    }
+
+    pub fn frame(&self, index: usize) -> Option<FrameView<'a>> {
+        self.frames().nth(index)
+    }
+
+    /// Tags attached to `frame`, or `None` if the frame does not exist.
+    pub fn tags(&self, frame: usize) -> Option<impl ExactSizeIterator<Item = TagView<'a>> + 'a> {
+        if frame >= self.num_frames() {
+            return None;
+        }
+        let bytes = self.bytes;
+        let num_tags = self.num_tags();
+        let start = self.layout.header.ofs_tags as usize + frame * num_tags * TAG_SIZE as usize;
+        Some((0..num_tags).map(move |i| {
+            let at = start + i * TAG_SIZE as usize;
+            TagView { bytes: &bytes[at..at + TAG_SIZE as usize] }
+        }))
+    }
+
+    pub fn surfaces<'v>(&'v self) -> impl ExactSizeIterator<Item = SurfaceView<'v>> + 'v {
+        let bytes = self.bytes;
+        self.layout.surfaces.iter().enumerate().map(move |(index, surface)| {
+            let start = surface.offset as usize;
+            let end = start + surface.header.ofs_end as usize;
+            SurfaceView {
+                bytes: &bytes[start..end],
+                index,
+                header: &surface.header,
+            }
+        })
+    }
+
+    pub fn surface<'v>(&'v self, index: usize) -> Option<SurfaceView<'v>> {
+        self.surfaces().nth(index)
+    }
+
+    /// Decodes the whole model into the owned representation.
+    pub fn to_owned(&self) -> Result<Md3> {
+        let mut buff = Source::new(Cursor::new(self.bytes))?;
+        Md3::read_layout(&mut buff, self.layout.clone())
+    }
+}
+
+#[derive(Copy,Clone)]
+pub struct FrameView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> FrameView<'a> {
+    pub fn min_bounds(&self) -> Vec3 {
+        vec3_at(self.bytes, 0)
+    }
+
+    pub fn max_bounds(&self) -> Vec3 {
+        vec3_at(self.bytes, 12)
+    }
+
+    pub fn local_origin(&self) -> Vec3 {
+        vec3_at(self.bytes, 24)
+    }
+
+    pub fn radius(&self) -> f32 {
+        f32_at(self.bytes, 36)
+    }
+
+    /// Raw name bytes up to the first NUL.
+    pub fn name(&self) -> &'a [u8] {
+        name_at(self.bytes, 40, 16)
+    }
+}
+
+#[derive(Copy,Clone)]
+pub struct TagView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> TagView<'a> {
+    /// Raw name bytes up to the first NUL.
+    pub fn name(&self) -> &'a [u8] {
+        name_at(self.bytes, 0, MAX_QPATH)
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        vec3_at(self.bytes, 64)
+    }
+
+    pub fn axis(&self) -> Mat3 {
+        Mat3::from_rows([vec3_at(self.bytes, 76),
+                         vec3_at(self.bytes, 88),
+                         vec3_at(self.bytes, 100)])
+    }
+}
+
+#[derive(Copy,Clone)]
+pub struct ShaderView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ShaderView<'a> {
+    /// Raw name bytes up to the first NUL.
+    pub fn name(&self) -> &'a [u8] {
+        name_at(self.bytes, 0, MAX_QPATH)
+    }
+
+    pub fn shader_index(&self) -> i32 {
+        s32_at(self.bytes, 64)
+    }
+}
+
+/// A surface of an `Md3View`. Offsets are relative to the surface start.
+#[derive(Copy,Clone)]
+pub struct SurfaceView<'a> {
+    bytes: &'a [u8],
+    index: usize,
+    header: &'a SurfaceHeader,
+}
+
+impl<'a> SurfaceView<'a> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn header(&self) -> &'a SurfaceHeader {
+        self.header
+    }
+
+    /// Raw name bytes up to the first NUL.
+    pub fn name(&self) -> &'a [u8] {
+        name_at(self.bytes, 4, MAX_QPATH)
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.header.num_frames as usize
+    }
+
+    pub fn num_verts(&self) -> usize {
+        self.header.num_verts as usize
+    }
+
+    pub fn shaders(&self) -> impl ExactSizeIterator<Item = ShaderView<'a>> + 'a {
+        let bytes = self.bytes;
+        let start = self.header.ofs_shaders as usize;
+        (0..self.header.num_shaders as usize).map(move |i| {
+            let at = start + i * SHADER_SIZE as usize;
+            ShaderView { bytes: &bytes[at..at + SHADER_SIZE as usize] }
+        })
+    }
+
+    pub fn triangles(&self) -> impl ExactSizeIterator<Item = Triangle> + 'a {
+        let bytes = self.bytes;
+        let start = self.header.ofs_triangles as usize;
+        (0..self.header.num_triangles as usize).map(move |i| {
+            let at = start + i * TRIANGLE_SIZE as usize;
+            Triangle { indexes: [s32_at(bytes, at), s32_at(bytes, at + 4), s32_at(bytes, at + 8)] }
+        })
+    }
+
+    pub fn tex_coords(&self) -> impl ExactSizeIterator<Item = TexCoord> + 'a {
+        let bytes = self.bytes;
+        let start = self.header.ofs_st as usize;
+        (0..self.num_verts()).map(move |i| {
+            let at = start + i * ST_SIZE as usize;
+            TexCoord { st: [f32_at(bytes, at), f32_at(bytes, at + 4)] }
+        })
+    }
+
+    /// Vertices of `frame`, or `None` if the frame does not exist.
+    pub fn vertices(&self, frame: usize) -> Option<impl ExactSizeIterator<Item = Vertex> + 'a> {
+        if frame >= self.num_frames() {
+            return None;
+        }
+        let bytes = self.bytes;
+        let num_verts = self.num_verts();
+        let start = self.header.ofs_xyznormal as usize +
+                    frame * num_verts * XYZNORMAL_SIZE as usize;
+        Some((0..num_verts).map(move |i| {
+            let at = start + i * XYZNORMAL_SIZE as usize;
+            Vertex {
+                x: s16_at(bytes, at),
+                y: s16_at(bytes, at + 2),
+                z: s16_at(bytes, at + 4),
+                normal: s16_at(bytes, at + 6),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Md3, Vec3};
+    use super::Md3View;
+    use test_model::{self, OFS_TAGS, OFS_SURFACES, SURFACE_NUM_FRAMES};
+
+    #[test]
+    fn to_owned_matches_from_bytes() {
+        for bytes in &[test_model::bytes(), test_model::padded_bytes()] {
+            let view = Md3View::new(bytes).unwrap();
+            let expected = Md3::from_bytes(bytes).unwrap();
+            assert_eq!(format!("{:?}", view.to_owned().unwrap()), format!("{:?}", expected));
+        }
+    }
+
+    #[test]
+    fn accessors() {
+        let bytes = test_model::padded_bytes();
+        let view = Md3View::new(&bytes).unwrap();
+        assert_eq!(view.num_frames(), 2);
+        assert_eq!(view.frame(1).unwrap().name(), b"frame1");
+        assert!(view.frame(2).is_none());
+
+        let tag = view.tags(1).unwrap().next().unwrap();
+        assert_eq!(tag.name(), b"tag_top");
+        assert_eq!(tag.origin(), Vec3::new(0.0, 0.0, 11.0));
+        assert!(view.tags(2).is_none());
+
+        let surface = view.surface(0).unwrap();
+        assert_eq!(surface.name(), b"body");
+        assert_eq!(surface.shaders().next().unwrap().name(), b"textures/body");
+        assert_eq!(surface.triangles().next().unwrap().indexes, [0, 1, 2]);
+        let vertex = surface.vertices(1).unwrap().nth(2).unwrap();
+        assert_eq!((vertex.x, vertex.y, vertex.z), (0, 16 * 64, 8 * 64));
+        assert!(surface.vertices(2).is_none());
+        assert!(view.surface(1).is_none());
+    }
+
+    #[test]
+    fn malformed_buffers_fail_new() {
+        let bytes = test_model::bytes();
+        for len in 0..bytes.len() {
+            assert!(Md3View::new(&bytes[..len]).is_err(), "length {}", len);
+        }
+
+        let surface = test_model::get_i32(&bytes, OFS_SURFACES) as usize;
+        let corruptions = [(OFS_TAGS, bytes.len() as i32),
+                           (OFS_SURFACES, -4),
+                           (surface + SURFACE_NUM_FRAMES, 1000)];
+        for &(at, value) in &corruptions {
+            let mut bad = bytes.clone();
+            test_model::set_i32(&mut bad, at, value);
+            assert!(Md3View::new(&bad).is_err(), "{} = {}", at, value);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use Section;
+
+/// A defect tolerated or repaired by a lenient load.
+#[derive(Debug,Clone,PartialEq)]
+pub enum WarningKind {
+    /// `ofs_eof` does not cover the data in the file.
+    EofMismatch { stored: i32, actual: u64 },
+    /// A surface's `num_frames` disagrees with the model header.
+    FrameCountMismatch { stored: i32, expected: i32 },
+    /// A frame's stored bounds do not contain its vertices.
+    BoundsMismatch { frame: usize },
+    /// A structure is stored before one the standard layout puts ahead of
+    /// it, such as tags before frames.
+    OutOfOrder { after: Section },
+    /// A name is not valid UTF-8. Its bytes are kept as stored.
+    InvalidName,
+    /// A name fills its whole field with no NUL terminator.
+    UnterminatedName,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WarningKind::EofMismatch { stored, actual } => {
+                write!(f, "ofs_eof is {} but the file is {} bytes", stored, actual)
+            }
+            WarningKind::FrameCountMismatch { stored, expected } => {
+                write!(f, "surface has {} frames, model has {}", stored, expected)
+            }
+            WarningKind::BoundsMismatch { frame } => {
+                write!(f, "bounds of frame {} do not contain its vertices", frame)
+            }
+            WarningKind::OutOfOrder { after } => {
+                write!(f, "stored before {}, which should come first", after)
+            }
+            WarningKind::InvalidName => write!(f, "name is not valid UTF-8"),
+            WarningKind::UnterminatedName => write!(f, "name is not NUL-terminated"),
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub offset: u64,
+    pub section: Section,
+    /// What the loader assumed in order to continue.
+    pub assumed: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} in {} at offset {}; {}",
+               self.kind,
+               self.section,
+               self.offset,
+               self.assumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Md3, Md3Error, Section};
+    use super::WarningKind;
+    use test_model::{self, FRAME_MAX_BOUNDS, FRAME_NAME, OFS_EOF, OFS_SURFACES,
+                     SURFACE_NUM_FRAMES};
+
+    fn kinds(bytes: &[u8]) -> Vec<WarningKind> {
+        let (_, warnings) = Md3::from_bytes_lenient(bytes).unwrap();
+        warnings.into_iter().map(|w| w.kind).collect()
+    }
+
+    #[test]
+    fn clean_model_has_no_warnings() {
+        assert_eq!(kinds(&test_model::bytes()), []);
+    }
+
+    #[test]
+    fn eof_mismatch() {
+        let mut bytes = test_model::bytes();
+        test_model::set_i32(&mut bytes, OFS_EOF, 200);
+        assert!(Md3::from_bytes(&bytes).is_err());
+        let (md3, warnings) = Md3::from_bytes_lenient(&bytes).unwrap();
+        assert_eq!(warnings[0].kind,
+                   WarningKind::EofMismatch {
+                       stored: 200,
+                       actual: bytes.len() as u64,
+                   });
+        assert_eq!(md3.header.ofs_eof as usize, bytes.len());
+    }
+
+    #[test]
+    fn surface_frame_count_mismatch() {
+        let mut bytes = test_model::bytes();
+        let surface = test_model::get_i32(&bytes, OFS_SURFACES) as usize;
+        test_model::set_i32(&mut bytes, surface + SURFACE_NUM_FRAMES, 1);
+        match Md3::from_bytes(&bytes) {
+            Err(Md3Error::FrameCountMismatch { section: Section::Surface(0), .. }) => {}
+            other => panic!("{:?}", other),
+        }
+
+        let (md3, warnings) = Md3::from_bytes_lenient(&bytes).unwrap();
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert_eq!(warnings[0].kind,
+                   WarningKind::FrameCountMismatch {
+                       stored: 1,
+                       expected: 2,
+                   });
+        assert_eq!(warnings[0].offset, surface as u64);
+        let surface = &md3.surfaces[0];
+        assert_eq!(surface.header.num_frames, 2);
+        assert_eq!(surface.vertices[1].len(), 3);
+        assert_eq!(surface.vertices[1][1].x, surface.vertices[0][1].x);
+    }
+
+    #[test]
+    fn bounds_mismatch() {
+        let mut bytes = test_model::bytes();
+        bytes[FRAME_MAX_BOUNDS..FRAME_MAX_BOUNDS + 4].copy_from_slice(&(-1.0f32).to_le_bytes());
+        Md3::from_bytes(&bytes).unwrap();
+        assert_eq!(kinds(&bytes), [WarningKind::BoundsMismatch { frame: 0 }]);
+    }
+
+    #[test]
+    fn invalid_name() {
+        let mut bytes = test_model::bytes();
+        bytes[FRAME_NAME] = 0xff;
+        let md3 = Md3::from_bytes(&bytes).unwrap();
+        assert_eq!(md3.frames[0].name.as_bytes()[0], 0xff);
+        assert_eq!(kinds(&bytes), [WarningKind::InvalidName]);
+    }
+}
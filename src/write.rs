@@ -0,0 +1,419 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{self, Write};
+
+use {Md3, Md3Error, Md3Header, Result, Section, SurfaceHeader};
+use {Frame, Shader, Surface, Tag, TexCoord, Triangle, Vec3, Vertex, FixedName};
+use {MD3_MAGIC, MD3_VERSION, HEADER_SIZE, FRAME_SIZE, TAG_SIZE, SURFACE_HEADER_SIZE,
+     SHADER_SIZE, TRIANGLE_SIZE, ST_SIZE, XYZNORMAL_SIZE};
+
+/// Copies `bytes` into `out` at `at`, returning false if they do not fit.
+fn place(out: &mut [u8], at: i64, bytes: &[u8]) -> bool {
+    if at < 0 || at as u64 + bytes.len() as u64 > out.len() as u64 {
+        return false;
+    }
+    out[at as usize..at as usize + bytes.len()].copy_from_slice(bytes);
+    true
+}
+
+fn to_i32(value: u64, section: Section) -> Result<i32> {
+    if value > i32::MAX as u64 {
+        Err(Md3Error::Inconsistent {
+            section,
+            reason: "size does not fit in an i32 field",
+        })
+    } else {
+        Ok(value as i32)
+    }
+}
+
+impl Md3 {
+    /// Serializes the model; see `to_bytes`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let bytes = self.to_bytes()?;
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Serializes the model.
+    ///
+    /// As long as the stored counts match the data and the stored offsets
+    /// still describe a valid file, the original layout and padding are kept,
+    /// so an unmodified model is written back byte for byte. Otherwise every
+    /// count and offset is recomputed and the standard layout is used.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let (header, surface_headers) = self.canonical_headers()?;
+        match self.write_preserved(&header, &surface_headers)? {
+            Some(bytes) => Ok(bytes),
+            None => self.write_canonical(&header, &surface_headers),
+        }
+    }
+
+    /// Whether the stored headers describe the standard layout with no
+    /// padding between or after the structures.
+    pub fn is_canonical_layout(&self) -> bool {
+        match self.canonical_headers() {
+            Ok((header, surface_headers)) => {
+                self.padding.is_empty() && self.header == header &&
+                self.surfaces.iter().zip(&surface_headers).all(|(s, h)| s.header == *h)
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn write_canonical(&self,
+                       header: &Md3Header,
+                       surface_headers: &[SurfaceHeader])
+                       -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(header.ofs_eof as usize);
+        Md3::write_md3_header(&mut out, header)?;
+        self.write_frames(&mut out)?;
+        self.write_tags(&mut out)?;
+        for (surface, surface_header) in self.surfaces.iter().zip(surface_headers) {
+            Md3::write_surface_header(&mut out, surface_header)?;
+            Md3::write_shaders(&mut out, surface)?;
+            Md3::write_triangles(&mut out, surface)?;
+            Md3::write_tex_coords(&mut out, surface)?;
+            Md3::write_vertices(&mut out, surface)?;
+        }
+        Ok(out)
+    }
+
+    /// Writes every structure at its stored offset, or returns `None` if the
+    /// stored layout no longer fits the data.
+    fn write_preserved(&self,
+                       header: &Md3Header,
+                       surface_headers: &[SurfaceHeader])
+                       -> Result<Option<Vec<u8>>> {
+        let stored = &self.header;
+        if stored.num_frames != header.num_frames || stored.num_tags != header.num_tags ||
+           stored.num_surfaces != header.num_surfaces || stored.ofs_eof < 0 {
+            return Ok(None);
+        }
+        for (surface, canonical) in self.surfaces.iter().zip(surface_headers) {
+            let h = &surface.header;
+            if h.num_frames != canonical.num_frames || h.num_shaders != canonical.num_shaders ||
+               h.num_verts != canonical.num_verts ||
+               h.num_triangles != canonical.num_triangles {
+                return Ok(None);
+            }
+        }
+
+        let len = self.padding
+            .iter()
+            .map(|p| p.offset + p.bytes.len() as u64)
+            .fold(stored.ofs_eof as u64, |a, b| a.max(b));
+        let mut out = vec![0; len as usize];
+        for padding in &self.padding {
+            place(&mut out, padding.offset as i64, &padding.bytes);
+        }
+
+        let mut chunk = Vec::new();
+        Md3::write_md3_header(&mut chunk, stored)?;
+        let mut fits = place(&mut out, 0, &chunk);
+        chunk.clear();
+        self.write_frames(&mut chunk)?;
+        fits &= place(&mut out, stored.ofs_frames as i64, &chunk);
+        chunk.clear();
+        self.write_tags(&mut chunk)?;
+        fits &= place(&mut out, stored.ofs_tags as i64, &chunk);
+
+        let mut pos = stored.ofs_surfaces as i64;
+        for surface in &self.surfaces {
+            let h = &surface.header;
+            chunk.clear();
+            Md3::write_surface_header(&mut chunk, h)?;
+            fits &= place(&mut out, pos, &chunk);
+            chunk.clear();
+            Md3::write_shaders(&mut chunk, surface)?;
+            fits &= place(&mut out, pos + h.ofs_shaders as i64, &chunk);
+            chunk.clear();
+            Md3::write_triangles(&mut chunk, surface)?;
+            fits &= place(&mut out, pos + h.ofs_triangles as i64, &chunk);
+            chunk.clear();
+            Md3::write_tex_coords(&mut chunk, surface)?;
+            fits &= place(&mut out, pos + h.ofs_st as i64, &chunk);
+            chunk.clear();
+            Md3::write_vertices(&mut chunk, surface)?;
+            fits &= place(&mut out, pos + h.ofs_xyznormal as i64, &chunk);
+            pos += h.ofs_end as i64;
+        }
+
+        if fits && Md3::validate(&out).is_valid() {
+            Ok(Some(out))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn write_frames<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for frame in &self.frames {
+            Md3::write_frame(out, frame)?;
+        }
+        Ok(())
+    }
+
+    fn write_tags<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for tag in &self.tags {
+            Md3::write_tag(out, tag)?;
+        }
+        Ok(())
+    }
+
+    fn write_shaders<W: Write>(out: &mut W, surface: &Surface) -> io::Result<()> {
+        for shader in &surface.shaders {
+            Md3::write_shader(out, shader)?;
+        }
+        Ok(())
+    }
+
+    fn write_triangles<W: Write>(out: &mut W, surface: &Surface) -> io::Result<()> {
+        for triangle in &surface.triangles {
+            Md3::write_triangle(out, triangle)?;
+        }
+        Ok(())
+    }
+
+    fn write_tex_coords<W: Write>(out: &mut W, surface: &Surface) -> io::Result<()> {
+        for tex_coord in &surface.tex_coords {
+            Md3::write_tex_coord(out, tex_coord)?;
+        }
+        Ok(())
+    }
+
+    fn write_vertices<W: Write>(out: &mut W, surface: &Surface) -> io::Result<()> {
+        for vertex in surface.vertices.iter().flatten() {
+            Md3::write_vertex(out, vertex)?;
+        }
+        Ok(())
+    }
+
+    /// Computes the headers for the standard layout: header, frames, tags and
+    /// surfaces, each surface holding shaders, triangles, st and xyznormal.
+    pub(crate) fn canonical_headers(&self) -> Result<(Md3Header, Vec<SurfaceHeader>)> {
+        let num_frames = self.frames.len() as u64;
+        let num_tags = if num_frames == 0 {
+            if !self.tags.is_empty() {
+                return Err(Md3Error::Inconsistent {
+                    section: Section::Tag(0),
+                    reason: "model has tags but no frames",
+                });
+            }
+            0
+        } else {
+            if !(self.tags.len() as u64).is_multiple_of(num_frames) {
+                return Err(Md3Error::Inconsistent {
+                    section: Section::Tag(self.tags.len() - 1),
+                    reason: "tag count is not a multiple of the frame count",
+                });
+            }
+            self.tags.len() as u64 / num_frames
+        };
+
+        let ofs_frames = HEADER_SIZE;
+        let ofs_tags = ofs_frames + num_frames * FRAME_SIZE;
+        let ofs_surfaces = ofs_tags + num_frames * num_tags * TAG_SIZE;
+
+        let mut pos = ofs_surfaces;
+        let mut surface_headers = Vec::with_capacity(self.surfaces.len());
+        for (j, surface) in self.surfaces.iter().enumerate() {
+            let section = Section::Surface(j);
+            if surface.vertices.len() as u64 != num_frames {
+                return Err(Md3Error::Inconsistent {
+                    section,
+                    reason: "surface frame count differs from the model",
+                });
+            }
+            let num_verts = surface.tex_coords.len() as u64;
+            for (frame, vertices) in surface.vertices.iter().enumerate() {
+                if vertices.len() as u64 != num_verts {
+                    return Err(Md3Error::Inconsistent {
+                        section: Section::Vertex {
+                            surface: j,
+                            frame,
+                            index: 0,
+                        },
+                        reason: "vertex count differs from the texture coordinate count",
+                    });
+                }
+            }
+            let num_shaders = surface.shaders.len() as u64;
+            let num_triangles = surface.triangles.len() as u64;
+
+            let ofs_shaders = SURFACE_HEADER_SIZE;
+            let ofs_triangles = ofs_shaders + num_shaders * SHADER_SIZE;
+            let ofs_st = ofs_triangles + num_triangles * TRIANGLE_SIZE;
+            let ofs_xyznormal = ofs_st + num_verts * ST_SIZE;
+            let ofs_end = ofs_xyznormal + num_frames * num_verts * XYZNORMAL_SIZE;
+
+            surface_headers.push(SurfaceHeader {
+                ident: MD3_MAGIC,
+                name: surface.header.name,
+                flags: surface.header.flags,
+                num_frames: to_i32(num_frames, section)?,
+                num_shaders: to_i32(num_shaders, section)?,
+                num_verts: to_i32(num_verts, section)?,
+                num_triangles: to_i32(num_triangles, section)?,
+                ofs_triangles: to_i32(ofs_triangles, section)?,
+                ofs_shaders: to_i32(ofs_shaders, section)?,
+                ofs_st: to_i32(ofs_st, section)?,
+                ofs_xyznormal: to_i32(ofs_xyznormal, section)?,
+                ofs_end: to_i32(ofs_end, section)?,
+            });
+            pos += ofs_end;
+        }
+
+        let section = Section::Header;
+        let header = Md3Header {
+            ident: MD3_MAGIC,
+            version: MD3_VERSION,
+            name: self.header.name,
+            flags: self.header.flags,
+            num_frames: to_i32(num_frames, section)?,
+            num_tags: to_i32(num_tags, section)?,
+            num_surfaces: to_i32(self.surfaces.len() as u64, section)?,
+            num_skins: self.header.num_skins,
+            ofs_frames: to_i32(ofs_frames, section)?,
+            ofs_tags: to_i32(ofs_tags, section)?,
+            ofs_surfaces: to_i32(ofs_surfaces, section)?,
+            ofs_eof: to_i32(pos, section)?,
+        };
+
+        Ok((header, surface_headers))
+    }
+
+    fn write_vec3<W: Write>(out: &mut W, v: &Vec3) -> io::Result<()> {
+        out.write_f32::<LittleEndian>(v.x)?;
+        out.write_f32::<LittleEndian>(v.y)?;
+        out.write_f32::<LittleEndian>(v.z)
+    }
+
+    fn write_name<W: Write, const N: usize>(out: &mut W, name: &FixedName<N>) -> io::Result<()> {
+        out.write_all(name.raw())
+    }
+
+    fn write_md3_header<W: Write>(out: &mut W, header: &Md3Header) -> io::Result<()> {
+        out.write_i32::<LittleEndian>(header.ident)?;
+        out.write_i32::<LittleEndian>(header.version)?;
+        Md3::write_name(out, &header.name)?;
+        out.write_i32::<LittleEndian>(header.flags)?;
+        out.write_i32::<LittleEndian>(header.num_frames)?;
+        out.write_i32::<LittleEndian>(header.num_tags)?;
+        out.write_i32::<LittleEndian>(header.num_surfaces)?;
+        out.write_i32::<LittleEndian>(header.num_skins)?;
+        out.write_i32::<LittleEndian>(header.ofs_frames)?;
+        out.write_i32::<LittleEndian>(header.ofs_tags)?;
+        out.write_i32::<LittleEndian>(header.ofs_surfaces)?;
+        out.write_i32::<LittleEndian>(header.ofs_eof)
+    }
+
+    fn write_frame<W: Write>(out: &mut W, frame: &Frame) -> io::Result<()> {
+        Md3::write_vec3(out, &frame.min_bounds)?;
+        Md3::write_vec3(out, &frame.max_bounds)?;
+        Md3::write_vec3(out, &frame.local_origin)?;
+        out.write_f32::<LittleEndian>(frame.radius)?;
+        Md3::write_name(out, &frame.name)
+    }
+
+    fn write_tag<W: Write>(out: &mut W, tag: &Tag) -> io::Result<()> {
+        Md3::write_name(out, &tag.name)?;
+        Md3::write_vec3(out, &tag.origin)?;
+        for axis in &tag.axis.rows {
+            Md3::write_vec3(out, axis)?;
+        }
+        Ok(())
+    }
+
+    fn write_surface_header<W: Write>(out: &mut W, header: &SurfaceHeader) -> io::Result<()> {
+        out.write_i32::<LittleEndian>(header.ident)?;
+        Md3::write_name(out, &header.name)?;
+        out.write_i32::<LittleEndian>(header.flags)?;
+        out.write_i32::<LittleEndian>(header.num_frames)?;
+        out.write_i32::<LittleEndian>(header.num_shaders)?;
+        out.write_i32::<LittleEndian>(header.num_verts)?;
+        out.write_i32::<LittleEndian>(header.num_triangles)?;
+        out.write_i32::<LittleEndian>(header.ofs_triangles)?;
+        out.write_i32::<LittleEndian>(header.ofs_shaders)?;
+        out.write_i32::<LittleEndian>(header.ofs_st)?;
+        out.write_i32::<LittleEndian>(header.ofs_xyznormal)?;
+        out.write_i32::<LittleEndian>(header.ofs_end)
+    }
+
+    fn write_shader<W: Write>(out: &mut W, shader: &Shader) -> io::Result<()> {
+        Md3::write_name(out, &shader.name)?;
+        out.write_i32::<LittleEndian>(shader.shader_index)
+    }
+
+    fn write_triangle<W: Write>(out: &mut W, triangle: &Triangle) -> io::Result<()> {
+        for index in &triangle.indexes {
+            out.write_i32::<LittleEndian>(*index)?;
+        }
+        Ok(())
+    }
+
+    fn write_tex_coord<W: Write>(out: &mut W, tex_coord: &TexCoord) -> io::Result<()> {
+        out.write_f32::<LittleEndian>(tex_coord.st[0])?;
+        out.write_f32::<LittleEndian>(tex_coord.st[1])
+    }
+
+    fn write_vertex<W: Write>(out: &mut W, vertex: &Vertex) -> io::Result<()> {
+        out.write_i16::<LittleEndian>(vertex.x)?;
+        out.write_i16::<LittleEndian>(vertex.y)?;
+        out.write_i16::<LittleEndian>(vertex.z)?;
+        out.write_i16::<LittleEndian>(vertex.normal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Md3, QPath};
+    use test_model;
+
+    #[test]
+    fn builder_output_is_canonical() {
+        let md3 = test_model::model();
+        assert!(md3.is_canonical_layout());
+        let bytes = md3.to_bytes().unwrap();
+        assert_eq!(bytes.len(), md3.header.ofs_eof as usize);
+        assert_eq!(Md3::from_bytes(&bytes).unwrap().to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn padding_round_trips_exactly() {
+        let bytes = test_model::padded_bytes();
+        let md3 = Md3::from_bytes(&bytes).unwrap();
+        assert!(!md3.is_canonical_layout());
+        assert_eq!(md3.padding.len(), 2);
+        assert_eq!(md3.to_bytes().unwrap(), bytes);
+
+        let mut written = Vec::new();
+        md3.write_to(&mut written).unwrap();
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn out_of_order_layout_is_rewritten() {
+        // Only layouts a strict load accepts are kept.
+        let (md3, _) = Md3::from_bytes_lenient(&test_model::swapped_bytes()).unwrap();
+        assert!(!md3.is_canonical_layout());
+        assert_eq!(md3.to_bytes().unwrap(), test_model::bytes());
+    }
+
+    #[test]
+    fn edited_model_falls_back_to_canonical_layout() {
+        let mut md3 = Md3::from_bytes(&test_model::padded_bytes()).unwrap();
+        md3.header.name = QPath::new("models/renamed.md3").unwrap();
+        md3.frames.pop();
+        md3.tags.pop();
+        md3.surfaces[0].vertices.pop();
+
+        let bytes = md3.to_bytes().unwrap();
+        let reloaded = Md3::from_bytes(&bytes).unwrap();
+        assert!(reloaded.is_canonical_layout());
+        assert!(reloaded.padding.is_empty());
+        assert_eq!(reloaded.header.name, "models/renamed.md3");
+        assert_eq!(reloaded.header.num_frames, 1);
+        assert_eq!(reloaded.surfaces[0].header.num_frames, 1);
+        assert_eq!(reloaded.tags.len(), 1);
+        assert_eq!(reloaded.to_bytes().unwrap(), bytes);
+    }
+}